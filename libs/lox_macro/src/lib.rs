@@ -1,6 +1,11 @@
+use convert_case::{Case, Casing};
 use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span};
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Fields, FieldsNamed};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Fields, FieldsNamed, FnArg, ItemEnum, ItemFn, LitStr,
+    Type,
+};
 
 #[proc_macro_derive(New)]
 pub fn new_function(input: TokenStream) -> TokenStream {
@@ -44,3 +49,238 @@ pub fn new_function(input: TokenStream) -> TokenStream {
 
     expanded.into()
 }
+
+/// 把普通的 Rust 函数暴露为 lox 内置函数
+///
+/// 原函数保持不变，另外生成 `<函数名>_native()`，返回可以交给
+/// `Interpreter::register_native` 注册的 `NativeFunction`。参数通过 `FromLox` 转换，
+/// 返回值可以是任意 `IntoLox` 类型或 `Result<T, Error>`。
+/// 末尾的 `Option` 参数可以省略；第一个参数是 `&mut Interpreter` 时传入解释器本身。
+/// 默认使用函数名作为 lox 中的名称，可以用 `#[lox_native(name = "...")]` 指定。
+#[proc_macro_attribute]
+pub fn lox_native(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let function = parse_macro_input!(item as ItemFn);
+    let mut lox_name = function.sig.ident.to_string();
+    let name_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            lox_name = meta.value()?.parse::<LitStr>()?.value();
+            Ok(())
+        } else {
+            Err(meta.error("unsupported lox_native property"))
+        }
+    });
+    parse_macro_input!(attr with name_parser);
+
+    match native_function(&function, &lox_name) {
+        Ok(native) => quote! {
+            #function
+            #native
+        }
+        .into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn native_function(function: &ItemFn, lox_name: &str) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &function.sig;
+    if !sig.generics.params.is_empty() || sig.asyncness.is_some() {
+        return Err(syn::Error::new_spanned(
+            sig,
+            "lox_native functions can't be generic or async",
+        ));
+    }
+
+    let mut types = Vec::new();
+    for input in &sig.inputs {
+        match input {
+            FnArg::Typed(arg) => types.push(arg.ty.as_ref()),
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "lox_native can't be used on methods",
+                ))
+            }
+        }
+    }
+    // 第一个参数是解释器时不占用 lox 参数
+    let takes_interpreter = types.first().is_some_and(|ty| is_interpreter(ty));
+    if takes_interpreter {
+        types.remove(0);
+    }
+
+    // 只有末尾连续的 Option 参数可以省略
+    let optional = types.iter().rev().take_while(|ty| is_option(ty)).count();
+    let required = types.len() - optional;
+    let total = types.len();
+    let arity = if optional == 0 {
+        quote! { ::lox::function::Arity::Fixed(#total) }
+    } else {
+        quote! { ::lox::function::Arity::Range(#required, #total) }
+    };
+
+    let args: Vec<_> = (0..total)
+        .map(|i| Ident::new(&format!("arg{}", i), Span::call_site()))
+        .collect();
+    let conversions = types.iter().zip(&args).enumerate().map(|(i, (ty, arg))| {
+        let position = i + 1;
+        quote! {
+            let #arg = ::lox::convert::from_argument::<#ty>(arguments.next(), #position)?;
+        }
+    });
+    // 不需要解释器时参数名以下划线开头，避免未使用变量的警告
+    let (interpreter, pass_interpreter) = if takes_interpreter {
+        (quote! { interpreter }, quote! { interpreter, })
+    } else {
+        (quote! { _interpreter }, quote! {})
+    };
+
+    let vis = &function.vis;
+    let name = &sig.ident;
+    let native_name = Ident::new(&format!("{}_native", name), name.span());
+    let doc = format!(" `{}` 对应的 lox 内置函数 `{}`", name, lox_name);
+    Ok(quote! {
+        #[doc = #doc]
+        #vis fn #native_name() -> ::lox::function::NativeFunction {
+            ::lox::function::NativeFunction::new(
+                #lox_name.to_string(),
+                #arity,
+                ::std::rc::Rc::new(
+                    |#interpreter: &mut ::lox::interpreter::Interpreter,
+                     arguments: ::std::vec::Vec<::lox::environment::Value>| {
+                        #[allow(unused_mut, unused_variables)]
+                        let mut arguments = arguments.into_iter();
+                        #(#conversions)*
+                        ::lox::convert::IntoLoxResult::into_lox_result(#name(#pass_interpreter #(#args),*))
+                    },
+                ),
+            )
+        }
+    })
+}
+
+/// 按最后一段路径判断类型，宏展开时无法得知类型别名
+fn last_segment(ty: &Type) -> Option<&syn::PathSegment> {
+    match ty {
+        Type::Path(path) => path.path.segments.last(),
+        _ => None,
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    last_segment(ty).is_some_and(|segment| segment.ident == "Option")
+}
+
+fn is_interpreter(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) => {
+            reference.mutability.is_some()
+                && last_segment(&reference.elem)
+                    .is_some_and(|segment| segment.ident == "Interpreter")
+        }
+        _ => false,
+    }
+}
+
+/// 从语法树节点列表生成枚举、访问者 trait 和 accept 分发
+///
+/// 枚举中的每个单元变体 `Foo` 展开为 `Foo(Foo)`，同时在访问者 trait 中生成
+/// `visit_foo`，并为枚举实现 accept trait 的 `accept` 方法。新增语法节点时只需要
+/// 定义节点结构体并在枚举中加一行：
+///
+/// ```ignore
+/// #[ast(visitor = ExprVisitor, accept = Expr)]
+/// #[derive(Debug, Clone)]
+/// pub enum ExprEnum {
+///     Binary,
+///     Grouping,
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ast(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut visitor = None;
+    let mut accept = None;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("visitor") {
+            visitor = Some(meta.value()?.parse::<Ident>()?);
+            Ok(())
+        } else if meta.path.is_ident("accept") {
+            accept = Some(meta.value()?.parse::<Ident>()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported ast property"))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+    let mut item = parse_macro_input!(item as ItemEnum);
+
+    let (Some(visitor), Some(accept)) = (visitor, accept) else {
+        return syn::Error::new_spanned(
+            &item.ident,
+            "ast requires both `visitor = ...` and `accept = ...`",
+        )
+        .to_compile_error()
+        .into();
+    };
+    match ast_enum(&mut item, &visitor, &accept) {
+        Ok(expanded) => expanded.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn ast_enum(
+    item: &mut ItemEnum,
+    visitor: &Ident,
+    accept: &Ident,
+) -> syn::Result<proc_macro2::TokenStream> {
+    // 访问方法的参数名取 accept trait 名的小写形式，例如 Expr 对应 expr
+    let param = Ident::new(&accept.to_string().to_case(Case::Snake), accept.span());
+    let vis = &item.vis;
+    let enum_name = &item.ident;
+
+    let mut methods = Vec::new();
+    let mut arms = Vec::new();
+    for variant in &mut item.variants {
+        let node = variant.ident.clone();
+        let ty: Type = match &variant.fields {
+            // 单元变体包装同名的节点结构体
+            Fields::Unit => {
+                variant.fields = Fields::Unnamed(syn::parse_quote! { (#node) });
+                syn::parse_quote! { #node }
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => fields.unnamed[0].ty.clone(),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "ast variants must be unit variants or wrap exactly one node",
+                ))
+            }
+        };
+        let method = Ident::new(
+            &format!("visit_{}", node.to_string().to_case(Case::Snake)),
+            node.span(),
+        );
+        methods.push(quote! {
+            fn #method(&mut self, #param: &#ty) -> Self::Output;
+        });
+        arms.push(quote! {
+            Self::#node(#param) => visitor.#method(#param),
+        });
+    }
+
+    Ok(quote! {
+        #vis trait #visitor {
+            type Output;
+            #(#methods)*
+        }
+
+        #item
+
+        impl #accept for #enum_name {
+            fn accept<R>(&self, visitor: &mut dyn #visitor<Output = R>) -> R {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
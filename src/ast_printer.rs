@@ -1,9 +1,10 @@
 use crate::expr::{
-    Assignment, Binary, Call, Expr, ExprEnum, ExprVisitor, Grouping, Literal, Logical, Unary,
-    Variable,
+    Assignment, Binary, Call, Expr, ExprEnum, ExprVisitor, Get, Grouping, Index, IndexSet,
+    Interpolation, Lambda, List, Literal, Logical, Map, Set, Super, This, Unary, Variable,
 };
 use crate::lex::Literal as LexLiteral;
 
+#[derive(Default)]
 pub struct AstPrinter {}
 
 impl ExprVisitor for AstPrinter {
@@ -43,6 +44,70 @@ impl ExprVisitor for AstPrinter {
     fn visit_call(&mut self, _expr: &Call) -> Self::Output {
         todo!()
     }
+
+    fn visit_get(&mut self, expr: &Get) -> Self::Output {
+        format!("(get {} {})", expr.object.accept(self), expr.name.lexeme)
+    }
+
+    fn visit_set(&mut self, expr: &Set) -> Self::Output {
+        format!(
+            "(set {} {} {})",
+            expr.object.accept(self),
+            expr.name.lexeme,
+            expr.value.accept(self)
+        )
+    }
+
+    fn visit_this(&mut self, _expr: &This) -> Self::Output {
+        "this".to_string()
+    }
+
+    fn visit_super(&mut self, expr: &Super) -> Self::Output {
+        format!("(super {})", expr.method.lexeme)
+    }
+
+    fn visit_list(&mut self, expr: &List) -> Self::Output {
+        let elements: Vec<&ExprEnum> = expr.elements.iter().collect();
+        self.parenthesize("list", &elements)
+    }
+
+    fn visit_map(&mut self, expr: &Map) -> Self::Output {
+        let entries: Vec<&ExprEnum> = expr
+            .entries
+            .iter()
+            .flat_map(|(key, _, value)| [key, value])
+            .collect();
+        self.parenthesize("map", &entries)
+    }
+
+    fn visit_index(&mut self, expr: &Index) -> Self::Output {
+        self.parenthesize("index", &[&expr.object, &expr.index])
+    }
+
+    fn visit_index_set(&mut self, expr: &IndexSet) -> Self::Output {
+        self.parenthesize("index=", &[&expr.object, &expr.index, &expr.value])
+    }
+
+    fn visit_lambda(&mut self, expr: &Lambda) -> Self::Output {
+        let mut parameters: Vec<String> = expr
+            .declaration
+            .parameters
+            .iter()
+            .map(|param| match &param.default {
+                Some(default) => format!("{}={}", param.name.lexeme, default.accept(self)),
+                None => param.name.lexeme.clone(),
+            })
+            .collect();
+        if let Some(rest) = &expr.declaration.rest {
+            parameters.push(format!("...{}", rest.lexeme));
+        }
+        format!("(fun ({}))", parameters.join(" "))
+    }
+
+    fn visit_interpolation(&mut self, expr: &Interpolation) -> Self::Output {
+        let parts: Vec<&ExprEnum> = expr.parts.iter().collect();
+        self.parenthesize("interpolate", &parts)
+    }
 }
 
 impl AstPrinter {
@@ -53,18 +118,18 @@ impl AstPrinter {
         expr.accept(self)
     }
 
-    fn parenthesize(&mut self, name: &str, exprs: &[&Box<ExprEnum>]) -> String {
+    fn parenthesize(&mut self, name: &str, exprs: &[&ExprEnum]) -> String {
         let mut str = String::new();
 
-        str.push_str("(");
+        str.push('(');
         str.push_str(name);
 
         exprs.iter().for_each(|expr| {
-            str.push_str(" ");
+            str.push(' ');
             str.push_str(&expr.accept(self));
         });
 
-        str.push_str(")");
+        str.push(')');
 
         str
     }
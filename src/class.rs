@@ -0,0 +1,114 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use lox_macro::New;
+
+use crate::{
+    environment::{Environment, Value},
+    error::{Error, RuntimeErrorKind},
+    function::{Arity, CallableInterface, Function},
+    interpreter::Interpreter,
+    lex::Token,
+};
+
+#[derive(Debug, New, Clone)]
+pub struct Class {
+    pub name: String,
+    superclass: Option<Rc<Class>>,
+    methods: HashMap<String, Function>,
+    // 类中所有方法共享声明类时的环境
+    closure: Rc<RefCell<Environment>>,
+}
+
+impl Class {
+    /// 先在当前类中查找方法，找不到再沿着父类链向上查找
+    pub fn find_method(&self, name: &str) -> Option<Function> {
+        self.methods.get(name).cloned().or_else(|| {
+            self.superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name))
+        })
+    }
+
+    /// 父类方法需要用父类自己的闭包环境进行绑定
+    pub fn bind_method(&self, name: &str, instance: Value) -> Option<Value> {
+        match self.methods.get(name) {
+            Some(method) => {
+                let env = method.bind(instance, Rc::clone(&self.closure));
+                Some(method.clone().into_value(env))
+            }
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.bind_method(name, instance)),
+        }
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.find_method("init")
+            .map_or(Arity::Fixed(0), |init| init.arity())
+    }
+
+    /// 调用类即创建实例，如果定义了 init 方法则绑定到新实例上执行
+    pub fn instantiate(
+        class: &Rc<Class>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let instance = Value::Instance(Rc::new(RefCell::new(Instance::new(
+            Rc::clone(class),
+            HashMap::new(),
+        ))));
+        if let Some(Value::Callable(init, env)) = class.bind_method("init", instance.clone()) {
+            init.call(interpreter, env, arguments)?;
+        }
+        Ok(instance)
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug, New)]
+pub struct Instance {
+    class: Rc<Class>,
+    fields: HashMap<String, Value>,
+}
+
+impl Instance {
+    /// 先查找字段，再查找方法；方法会绑定 this 到当前实例
+    pub fn get(instance: &Rc<RefCell<Instance>>, name: &Token) -> Result<Value, Error> {
+        let this = instance.borrow();
+        if let Some(value) = this.fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+        match this
+            .class
+            .bind_method(&name.lexeme, Value::Instance(Rc::clone(instance)))
+        {
+            Some(method) => Ok(method),
+            None => Err(Error::RuntimeError(
+                RuntimeErrorKind::Property,
+                name.clone(),
+                format!("Undefined property '{}'.", name.lexeme),
+            )),
+        }
+    }
+
+    pub fn set(&mut self, name: &Token, value: Value) {
+        self.fields.insert(name.lexeme.clone(), value);
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
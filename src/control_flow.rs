@@ -0,0 +1,23 @@
+use crate::environment::Value;
+
+/// 语句执行后的控制流信号
+///
+/// 语句执行器通过返回值而不是错误来传递非局部跳转，
+/// 错误只用来表示真正的失败。
+#[derive(Debug, Clone)]
+pub enum ControlFlow {
+    /// 正常执行完毕，继续执行下一条语句
+    Normal,
+    /// 从当前函数返回
+    Return(Value),
+    /// 跳出最内层循环
+    Break,
+    /// 结束本次循环，进入下一次迭代
+    Continue,
+}
+
+impl ControlFlow {
+    pub fn is_normal(&self) -> bool {
+        matches!(self, Self::Normal)
+    }
+}
@@ -0,0 +1,451 @@
+use std::{cell::RefCell, collections::HashMap, hash::Hash, rc::Rc};
+
+use indexmap::IndexMap;
+
+use crate::{
+    environment::Value,
+    error::Error,
+    function::{Arity, NativeFunction},
+    interpreter::Interpreter,
+    lex::Literal,
+};
+
+/// 从 lox 值转换为 Rust 类型，类型不符时返回 TypeError
+pub trait FromLox: Sized {
+    fn from_lox(value: Value) -> Result<Self, Error>;
+}
+
+/// 从 Rust 类型转换为 lox 值
+pub trait IntoLox {
+    fn into_lox(self) -> Value;
+}
+
+/// 内置函数的返回值，既可以直接返回值，也可以返回 Result
+pub trait IntoLoxResult {
+    fn into_lox_result(self) -> Result<Value, Error>;
+}
+
+impl<T: IntoLox> IntoLoxResult for T {
+    fn into_lox_result(self) -> Result<Value, Error> {
+        Ok(self.into_lox())
+    }
+}
+
+impl<T: IntoLox> IntoLoxResult for Result<T, Error> {
+    fn into_lox_result(self) -> Result<Value, Error> {
+        self.map(IntoLox::into_lox)
+    }
+}
+
+/// 在嵌套转换的错误信息前加上出错的位置，例如 "Element 1: Expected a number but got string."
+fn in_context(error: Error, context: impl FnOnce() -> String) -> Error {
+    match error {
+        Error::NativeError(kind, msg) => {
+            Error::NativeError(kind, format!("{}: {}", context(), msg))
+        }
+        error => error,
+    }
+}
+
+/// 转换第 position 个实参（从 1 开始），缺少的实参视为 nil，以便 Option 参数可以省略
+pub fn from_argument<T: FromLox>(value: Option<Value>, position: usize) -> Result<T, Error> {
+    T::from_lox(value.unwrap_or(Value::Literal(Literal::Nil)))
+        .map_err(|e| in_context(e, || format!("Argument {}", position)))
+}
+
+impl FromLox for Value {
+    fn from_lox(value: Value) -> Result<Self, Error> {
+        Ok(value)
+    }
+}
+
+impl IntoLox for Value {
+    fn into_lox(self) -> Value {
+        self
+    }
+}
+
+impl FromLox for f64 {
+    fn from_lox(value: Value) -> Result<Self, Error> {
+        value.as_number()
+    }
+}
+
+impl IntoLox for f64 {
+    fn into_lox(self) -> Value {
+        Value::Literal(Literal::Number(self))
+    }
+}
+
+impl FromLox for bool {
+    fn from_lox(value: Value) -> Result<Self, Error> {
+        value.as_boolean()
+    }
+}
+
+impl IntoLox for bool {
+    fn into_lox(self) -> Value {
+        Value::Literal(Literal::Boolean(self))
+    }
+}
+
+impl FromLox for String {
+    fn from_lox(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Literal(Literal::String(s)) => Ok(s),
+            value => Err(Value::type_error("a string", &value)),
+        }
+    }
+}
+
+impl IntoLox for String {
+    fn into_lox(self) -> Value {
+        Value::Literal(Literal::String(self))
+    }
+}
+
+impl IntoLox for &str {
+    fn into_lox(self) -> Value {
+        Value::Literal(Literal::String(self.to_string()))
+    }
+}
+
+impl IntoLox for () {
+    fn into_lox(self) -> Value {
+        Value::Literal(Literal::Nil)
+    }
+}
+
+/// nil 对应 None
+impl<T: FromLox> FromLox for Option<T> {
+    fn from_lox(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Literal(Literal::Nil) => Ok(None),
+            value => T::from_lox(value).map(Some),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+    fn into_lox(self) -> Value {
+        self.map_or(Value::Literal(Literal::Nil), IntoLox::into_lox)
+    }
+}
+
+/// 列表转换时会复制元素，之后对 Vec 的修改不会影响原列表
+impl<T: FromLox> FromLox for Vec<T> {
+    fn from_lox(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::List(list) => list
+                .borrow()
+                .iter()
+                .enumerate()
+                .map(|(i, element)| {
+                    T::from_lox(element.clone())
+                        .map_err(|e| in_context(e, || format!("Element {}", i)))
+                })
+                .collect(),
+            value => Err(Value::type_error("a list", &value)),
+        }
+    }
+}
+
+impl<T: IntoLox> IntoLox for Vec<T> {
+    fn into_lox(self) -> Value {
+        let elements = self.into_iter().map(IntoLox::into_lox).collect();
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+}
+
+impl<K: FromLox + Eq + Hash, V: FromLox> FromLox for HashMap<K, V> {
+    fn from_lox(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Map(map) => map
+                .borrow()
+                .iter()
+                .map(|(key, value)| {
+                    let context = || format!("Key {}", Value::Literal(key.clone()));
+                    let key = K::from_lox(Value::Literal(key.clone()))
+                        .map_err(|e| in_context(e, context))?;
+                    let value = V::from_lox(value.clone()).map_err(|e| in_context(e, context))?;
+                    Ok((key, value))
+                })
+                .collect(),
+            value => Err(Value::type_error("a map", &value)),
+        }
+    }
+}
+
+/// 映射的键只能是字符串、数字或布尔值，这里只支持最常见的字符串键
+impl<V: IntoLox> IntoLox for HashMap<String, V> {
+    fn into_lox(self) -> Value {
+        let mut entries: Vec<_> = self.into_iter().collect();
+        // HashMap 没有顺序，按键排序让结果稳定
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let map: IndexMap<_, _> = entries
+            .into_iter()
+            .map(|(key, value)| (Literal::String(key), value.into_lox()))
+            .collect();
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+}
+
+/// 元组对应定长的列表
+macro_rules! impl_tuple {
+    ($len:expr; $($name:ident $index:tt),+) => {
+        impl<$($name: FromLox),+> FromLox for ($($name,)+) {
+            fn from_lox(value: Value) -> Result<Self, Error> {
+                let elements = match value {
+                    Value::List(list) if list.borrow().len() == $len => list.borrow().clone(),
+                    value => {
+                        return Err(Value::type_error(
+                            concat!("a list of ", stringify!($len), " elements"),
+                            &value,
+                        ))
+                    }
+                };
+                let mut elements = elements.into_iter();
+                Ok(($(
+                    $name::from_lox(elements.next().unwrap_or(Value::Literal(Literal::Nil)))
+                        .map_err(|e| in_context(e, || format!("Element {}", $index)))?,
+                )+))
+            }
+        }
+
+        impl<$($name: IntoLox),+> IntoLox for ($($name,)+) {
+            fn into_lox(self) -> Value {
+                Vec::from([$(self.$index.into_lox()),+]).into_lox()
+            }
+        }
+    };
+}
+
+impl_tuple!(1; A 0);
+impl_tuple!(2; A 0, B 1);
+impl_tuple!(3; A 0, B 1, C 2);
+impl_tuple!(4; A 0, B 1, C 2, D 3);
+
+/// 可以包装为内置函数的 Rust 函数，Args 是参数类型组成的元组
+pub trait IntoNative<Args> {
+    fn into_native(self, name: impl Into<String>) -> NativeFunction;
+}
+
+/// 参数个数固定，调用时逐个转换参数，转换失败会指出是第几个参数
+macro_rules! impl_into_native {
+    ($len:expr; $($name:ident $arg:ident $index:tt),*) => {
+        impl<F, R, $($name),*> IntoNative<($($name,)*)> for F
+        where
+            F: Fn($($name),*) -> R + 'static,
+            R: IntoLoxResult,
+            $($name: FromLox,)*
+        {
+            fn into_native(self, name: impl Into<String>) -> NativeFunction {
+                NativeFunction::new(
+                    name.into(),
+                    Arity::Fixed($len),
+                    Rc::new(move |_: &mut Interpreter, arguments: Vec<Value>| {
+                        #[allow(unused_mut, unused_variables)]
+                        let mut arguments = arguments.into_iter();
+                        $(let $arg = from_argument::<$name>(arguments.next(), $index + 1)?;)*
+                        self($($arg),*).into_lox_result()
+                    }),
+                )
+            }
+        }
+    };
+}
+
+impl_into_native!(0;);
+impl_into_native!(1; A a 0);
+impl_into_native!(2; A a 0, B b 1);
+impl_into_native!(3; A a 0, B b 1, C c 2);
+impl_into_native!(4; A a 0, B b 1, C c 2, D d 3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::RuntimeErrorKind;
+    use lox_macro::lox_native;
+
+    fn type_error_message<T: FromLox + std::fmt::Debug>(value: Value) -> String {
+        match T::from_lox(value) {
+            Err(Error::NativeError(RuntimeErrorKind::Type, msg)) => msg,
+            r => panic!("expected a type error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = vec![(1.0, "a".to_string()), (2.0, "b".to_string())].into_lox();
+        assert_eq!(value.to_string(), r#"[[1, "a"], [2, "b"]]"#);
+        let back: Vec<(f64, String)> = FromLox::from_lox(value).unwrap();
+        assert_eq!(back, [(1.0, "a".into()), (2.0, "b".into())]);
+
+        let map = HashMap::from([("b".to_string(), Some(true)), ("a".to_string(), None)]);
+        let value = map.clone().into_lox();
+        assert_eq!(value.to_string(), "{\"a\": nil, \"b\": true}");
+        assert_eq!(
+            HashMap::<String, Option<bool>>::from_lox(value).unwrap(),
+            map
+        );
+
+        assert_eq!(Option::<f64>::from_lox(().into_lox()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert_eq!(
+            type_error_message::<f64>("1".into_lox()),
+            "Expected a number but got string."
+        );
+        assert_eq!(
+            type_error_message::<Vec<f64>>(vec![1.0.into_lox(), true.into_lox()].into_lox()),
+            "Element 1: Expected a number but got boolean."
+        );
+        assert_eq!(
+            type_error_message::<HashMap<String, String>>(
+                HashMap::from([("k".to_string(), 1.0)]).into_lox()
+            ),
+            "Key k: Expected a string but got number."
+        );
+        assert_eq!(
+            type_error_message::<(f64, f64)>(vec![1.0].into_lox()),
+            "Expected a list of 2 elements but got list."
+        );
+    }
+
+    #[test]
+    fn test_typed_natives() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("hypot", |a: f64, b: f64| (a * a + b * b).sqrt());
+        interpreter.define_native("join", |parts: Vec<String>, sep: Option<String>| {
+            parts.join(&sep.unwrap_or_default())
+        });
+        interpreter.define_native("checked_sqrt", |n: f64| {
+            if n < 0.0 {
+                Err(Error::NativeError(
+                    RuntimeErrorKind::Type,
+                    "Can't take the square root of a negative number.".into(),
+                ))
+            } else {
+                Ok(n.sqrt())
+            }
+        });
+
+        let call = |interpreter: &mut Interpreter, name: &str, arguments: Vec<Value>| {
+            interpreter.call_global(name, arguments)
+        };
+        let hypot = call(
+            &mut interpreter,
+            "hypot",
+            vec![3.0.into_lox(), 4.0.into_lox()],
+        );
+        assert_eq!(hypot.unwrap().to_string(), "5");
+        let joined = call(
+            &mut interpreter,
+            "join",
+            vec![vec!["a", "b"].into_lox(), "-".into_lox()],
+        );
+        assert_eq!(joined.unwrap().to_string(), "a-b");
+
+        match call(
+            &mut interpreter,
+            "join",
+            vec![vec![1.0].into_lox(), ().into_lox()],
+        ) {
+            Err(Error::NativeError(_, msg)) => {
+                assert_eq!(
+                    msg,
+                    "Argument 1: Element 0: Expected a string but got number."
+                )
+            }
+            r => panic!("expected a native error, got {:?}", r),
+        }
+        assert!(call(&mut interpreter, "checked_sqrt", vec![(-1.0).into_lox()]).is_err());
+        assert!(matches!(
+            call(&mut interpreter, "hypot", vec![1.0.into_lox()]),
+            Err(Error::NativeError(RuntimeErrorKind::Arity, _))
+        ));
+    }
+
+    /// 末尾的可选参数可以省略
+    #[lox_native(name = "repeat")]
+    fn repeat_string(text: String, times: Option<f64>) -> Result<String, Error> {
+        let times = times.unwrap_or(2.0);
+        if times < 0.0 {
+            return Err(Error::NativeError(
+                RuntimeErrorKind::Type,
+                "Can't repeat a negative number of times.".into(),
+            ));
+        }
+        Ok(text.repeat(times as usize))
+    }
+
+    /// 第一个参数是解释器时不计入 arity
+    #[lox_native]
+    fn lookup(interpreter: &mut Interpreter, name: String) -> Option<Value> {
+        interpreter.get_global(&name)
+    }
+
+    #[lox_native]
+    fn pair(a: f64, b: bool) -> (f64, bool) {
+        (a, b)
+    }
+
+    #[test]
+    fn test_lox_native_attribute() {
+        let native = repeat_string_native();
+        assert_eq!(native.name, "repeat");
+        assert_eq!(native.arity, Arity::Range(1, 2));
+        assert_eq!(pair_native().arity, Arity::Fixed(2));
+        assert_eq!(lookup_native().arity, Arity::Fixed(1));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.register_native(native);
+        interpreter.register_native(pair_native());
+        interpreter.register_native(lookup_native());
+        let call = |interpreter: &mut Interpreter, name: &str, arguments: Vec<Value>| {
+            interpreter
+                .call_global(name, arguments)
+                .map(|value| value.to_string())
+        };
+        assert_eq!(
+            call(&mut interpreter, "repeat", vec!["ab".into_lox()]).unwrap(),
+            "abab"
+        );
+        assert_eq!(
+            call(
+                &mut interpreter,
+                "repeat",
+                vec!["a".into_lox(), 3.0.into_lox()]
+            )
+            .unwrap(),
+            "aaa"
+        );
+        assert_eq!(
+            call(
+                &mut interpreter,
+                "pair",
+                vec![1.0.into_lox(), true.into_lox()]
+            )
+            .unwrap(),
+            "[1, true]"
+        );
+        match call(
+            &mut interpreter,
+            "pair",
+            vec![1.0.into_lox(), 1.0.into_lox()],
+        ) {
+            Err(Error::NativeError(_, msg)) => {
+                assert_eq!(msg, "Argument 2: Expected a boolean but got number.")
+            }
+            r => panic!("expected a native error, got {:?}", r),
+        }
+        assert!(call(
+            &mut interpreter,
+            "repeat",
+            vec!["a".into_lox(), (-1.0).into_lox()]
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt::{self, Display};
+
+use crate::{
+    error::{Error, StackFrame},
+    lex::{Span, TokenType},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// 一条可以展示给用户的诊断信息，错误码保持稳定，便于检索和工具处理
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub help: Option<String>,
+    // 运行时错误的调用栈，最内层在前
+    pub trace: Vec<StackFrame>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span,
+            help: None,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+
+impl From<&Error> for Diagnostic {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::InternalError(msg) => Diagnostic::error("E0000", msg, None),
+            Error::LexError(span, msg) => Diagnostic::error("E0001", msg, Some(*span)),
+            Error::ParseError(token, msg) => {
+                let diagnostic = Diagnostic::error("E0002", msg, Some(token.span));
+                if token.token_type == TokenType::Eof {
+                    diagnostic.with_help("the file ended before this code was complete")
+                } else {
+                    diagnostic
+                }
+            }
+            Error::ResolveError(token, msg) => Diagnostic::error("E0003", msg, Some(token.span)),
+            Error::RuntimeError(kind, token, msg) => {
+                Diagnostic::error(kind.code(), format!("{}: {}", kind, msg), Some(token.span))
+            }
+            Error::NativeError(kind, msg) => {
+                Diagnostic::error(kind.code(), format!("{}: {}", kind, msg), None)
+            }
+            Error::Traced(error, trace) => {
+                let mut diagnostic = Diagnostic::from(error.as_ref());
+                diagnostic.trace = trace.clone();
+                diagnostic
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// 附带源码片段和下划线，面向人阅读
+    Rich,
+    /// 每条诊断一行，不带源码片段
+    Plain,
+    /// 每条诊断一个 JSON 对象，供 CI 和编辑器集成使用
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rich" => Ok(Format::Rich),
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown diagnostics format: {}", s)),
+        }
+    }
+}
+
+/// 将诊断信息渲染为文本
+pub struct Renderer<'a> {
+    filename: &'a str,
+    source: &'a str,
+    format: Format,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(filename: &'a str, source: &'a str, format: Format) -> Self {
+        Self {
+            filename,
+            source,
+            format,
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        match self.format {
+            Format::Rich => self.render_rich(diagnostic),
+            Format::Plain => self.render_plain(diagnostic),
+            Format::Json => self.render_json(diagnostic),
+        }
+    }
+
+    fn render_rich(&self, diagnostic: &Diagnostic) -> String {
+        let mut out = format!(
+            "{}[{}]: {}\n",
+            diagnostic.severity, diagnostic.code, diagnostic.message
+        );
+        if let Some(span) = diagnostic.span {
+            let gutter = " ".repeat(span.line.to_string().len());
+            out.push_str(&format!(
+                "{}--> {}:{}:{}\n",
+                gutter, self.filename, span.line, span.column
+            ));
+            if let Some(line) = self.source.lines().nth(span.line - 1) {
+                out.push_str(&format!("{} |\n", gutter));
+                out.push_str(&format!("{} | {}\n", span.line, line));
+                out.push_str(&format!(
+                    "{} | {}{}\n",
+                    gutter,
+                    " ".repeat(span.column - 1),
+                    "^".repeat(self.underline_width(&span, line))
+                ));
+            }
+            if let Some(help) = &diagnostic.help {
+                out.push_str(&format!("{} = help: {}\n", gutter, help));
+            }
+        } else if let Some(help) = &diagnostic.help {
+            out.push_str(&format!("  = help: {}\n", help));
+        }
+        for frame in &diagnostic.trace {
+            out.push_str(&format!(
+                "    at {} ({}:{})\n",
+                frame.function, self.filename, frame.line
+            ));
+        }
+        out
+    }
+
+    fn render_plain(&self, diagnostic: &Diagnostic) -> String {
+        let location = match diagnostic.span {
+            Some(span) => format!("{}:{}:{}", self.filename, span.line, span.column),
+            None => self.filename.to_string(),
+        };
+        let mut out = format!(
+            "{}: {}[{}]: {}",
+            location, diagnostic.severity, diagnostic.code, diagnostic.message
+        );
+        if let Some(help) = &diagnostic.help {
+            out.push_str(&format!(" (help: {})", help));
+        }
+        out
+    }
+
+    fn render_json(&self, diagnostic: &Diagnostic) -> String {
+        let (line, column, span) = match diagnostic.span {
+            Some(span) => (
+                span.line.to_string(),
+                span.column.to_string(),
+                format!("{{\"start\":{},\"end\":{}}}", span.start, span.end),
+            ),
+            None => ("null".into(), "null".into(), "null".into()),
+        };
+        let help = diagnostic
+            .help
+            .as_ref()
+            .map_or("null".to_string(), |help| json_string(help));
+        let trace: Vec<String> = diagnostic
+            .trace
+            .iter()
+            .map(|frame| {
+                format!(
+                    "{{\"function\":{},\"line\":{}}}",
+                    json_string(&frame.function),
+                    frame.line
+                )
+            })
+            .collect();
+        format!(
+            "{{\"severity\":{},\"code\":{},\"message\":{},\"file\":{},\"line\":{},\"column\":{},\"span\":{},\"help\":{},\"trace\":[{}]}}",
+            json_string(&diagnostic.severity.to_string()),
+            json_string(diagnostic.code),
+            json_string(&diagnostic.message),
+            json_string(self.filename),
+            line,
+            column,
+            span,
+            help,
+            trace.join(",")
+        )
+    }
+
+    /// 下划线只覆盖区间在起始行内的部分，至少一个字符宽
+    fn underline_width(&self, span: &Span, line: &str) -> usize {
+        let rest = line.chars().count().saturating_sub(span.column - 1);
+        let text = self.source.get(span.start..span.end).unwrap_or_default();
+        let width = text.lines().next().unwrap_or_default().chars().count();
+        width.min(rest).max(1)
+    }
+}
+
+/// 转义为 JSON 字符串字面量
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_snippet() {
+        let source = "var a = 1;\nprint a +;\n";
+        let span = Span::new(20, 21, 2, 10);
+        let diagnostic = Diagnostic::error("E0002", "Expected expression.", Some(span))
+            .with_help("remove the trailing operator");
+
+        let rich = Renderer::new("test.lox", source, Format::Rich).render(&diagnostic);
+        assert_eq!(
+            rich,
+            "error[E0002]: Expected expression.\n \
+             --> test.lox:2:10\n  \
+             |\n\
+             2 | print a +;\n  \
+             |          ^\n  \
+             = help: remove the trailing operator\n"
+        );
+
+        let plain = Renderer::new("test.lox", source, Format::Plain).render(&diagnostic);
+        assert_eq!(
+            plain,
+            "test.lox:2:10: error[E0002]: Expected expression. (help: remove the trailing operator)"
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        let source = "print \"a\" + 1;";
+        let diagnostic = Diagnostic::error(
+            "E0002",
+            "Operand must be \"numbers\".",
+            Some(Span::new(10, 11, 1, 11)),
+        );
+        let json = Renderer::new("dir\\test.lox", source, Format::Json).render(&diagnostic);
+        assert_eq!(
+            json,
+            r#"{"severity":"error","code":"E0002","message":"Operand must be \"numbers\".","file":"dir\\test.lox","line":1,"column":11,"span":{"start":10,"end":11},"help":null,"trace":[]}"#
+        );
+
+        let diagnostic = Diagnostic::error("E0004", "boom", None);
+        let json = Renderer::new("test.lox", source, Format::Json).render(&diagnostic);
+        assert!(json.contains(r#""line":null,"column":null,"span":null"#));
+    }
+}
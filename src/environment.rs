@@ -1,38 +1,190 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Display},
+    rc::Rc,
+};
 
-use crate::{error::Error, function::Callable, lex::Literal};
+use indexmap::IndexMap;
+
+use crate::{
+    class::Instance,
+    error::{Error, RuntimeErrorKind},
+    function::Callable,
+    lex::{Literal, Token},
+};
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Literal(Literal),
     Callable(Callable, Rc<RefCell<Environment>>),
+    Instance(Rc<RefCell<Instance>>),
+    // 列表和映射是引用类型，赋值和传参共享同一个底层容器
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<IndexMap<Literal, Value>>>),
 }
 
 impl Value {
     pub fn as_literal(&self) -> Result<Literal, Error> {
         match self {
             Self::Literal(literal) => Ok(literal.clone()),
-            _ => Err(Error::RuntimeError("Value is not a literal".to_string())),
+            _ => Err(Error::InternalError("Value is not a literal".to_string())),
         }
     }
 
     pub fn as_callable(&self) -> Result<(Callable, Rc<RefCell<Environment>>), Error> {
         match self {
             Self::Callable(callable, env) => Ok((callable.clone(), env.clone())),
-            _ => Err(Error::RuntimeError("Value is not a callable".to_string())),
+            _ => Err(Error::InternalError("Value is not a callable".to_string())),
         }
     }
-}
 
-impl ToString for Value {
-    fn to_string(&self) -> String {
+    pub fn as_number(&self) -> Result<f64, Error> {
         match self {
-            Self::Literal(literal) => format!("{}", literal),
-            Self::Callable(callable, _) => callable.to_string(),
+            Self::Literal(Literal::Number(n)) => Ok(*n),
+            _ => Err(Self::type_error("a number", self)),
+        }
+    }
+
+    pub fn as_boolean(&self) -> Result<bool, Error> {
+        match self {
+            Self::Literal(Literal::Boolean(b)) => Ok(*b),
+            _ => Err(Self::type_error("a boolean", self)),
+        }
+    }
+
+    pub fn as_string(&self) -> Result<String, Error> {
+        match self {
+            Self::Literal(Literal::String(s)) => Ok(s.clone()),
+            _ => Err(Self::type_error("a string", self)),
+        }
+    }
+
+    /// 值的类型名称，用于错误信息
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Literal(Literal::Number(_)) => "number",
+            Self::Literal(Literal::String(_)) => "string",
+            Self::Literal(Literal::Boolean(_)) => "boolean",
+            Self::Literal(Literal::Nil) => "nil",
+            Self::Callable(Callable::Class(_), _) => "class",
+            Self::Callable(..) => "function",
+            Self::Instance(_) => "instance",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
+        }
+    }
+
+    // 宿主程序读取值时类型不符，没有源码位置可以指向
+    pub(crate) fn type_error(expected: &str, value: &Value) -> Error {
+        Error::NativeError(
+            RuntimeErrorKind::Type,
+            format!("Expected {} but got {}.", expected, value.type_name()),
+        )
+    }
+
+    /// 转换为映射的键，只有字符串、数字和布尔值可以作为键
+    pub fn as_key(&self) -> Option<Literal> {
+        match self {
+            Self::Literal(Literal::Number(n)) if n.is_nan() => None,
+            // 0.0 和 -0.0 相等，哈希值也必须相同
+            Self::Literal(Literal::Number(n)) if *n == 0.0 => Some(Literal::Number(0.0)),
+            Self::Literal(
+                literal @ (Literal::String(_) | Literal::Number(_) | Literal::Boolean(_)),
+            ) => Some(literal.clone()),
+            _ => None,
+        }
+    }
+
+    /// lox 中只有 false 和 nil 是假值
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Self::Literal(literal) => literal.is_truthy(),
+            _ => true,
+        }
+    }
+
+    /// 字面量按值比较，函数、类、实例、列表和映射按引用比较
+    pub fn is_equal(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Self::Literal(a), Self::Literal(b)) => a.is_equal(b),
+            // 同一个声明在不同的闭包环境中是不同的函数
+            (Self::Callable(a, a_env), Self::Callable(b, b_env)) => {
+                a.ptr_eq(b) && Rc::ptr_eq(a_env, b_env)
+            }
+            (Self::Instance(a), Self::Instance(b)) => Rc::ptr_eq(a, b),
+            (Self::List(a), Self::List(b)) => Rc::ptr_eq(a, b),
+            (Self::Map(a), Self::Map(b)) => Rc::ptr_eq(a, b),
+            _ => false,
         }
     }
 }
 
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_value(f, self, &mut Vec::new())
+    }
+}
+
+/// seen 保存正在打印的列表和映射，容器包含自身时打印 [...] 或 {...}，避免无限递归
+fn write_value(
+    f: &mut fmt::Formatter<'_>,
+    value: &Value,
+    seen: &mut Vec<*const ()>,
+) -> fmt::Result {
+    match value {
+        Value::Literal(literal) => write!(f, "{}", literal),
+        Value::Callable(callable, _) => write!(f, "{}", callable),
+        Value::Instance(instance) => write!(f, "{}", instance.borrow()),
+        Value::List(list) => {
+            let ptr = Rc::as_ptr(list) as *const ();
+            if seen.contains(&ptr) {
+                return write!(f, "[...]");
+            }
+            seen.push(ptr);
+            write!(f, "[")?;
+            for (i, element) in list.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_element(f, element, seen)?;
+            }
+            seen.pop();
+            write!(f, "]")
+        }
+        Value::Map(map) => {
+            let ptr = Rc::as_ptr(map) as *const ();
+            if seen.contains(&ptr) {
+                return write!(f, "{{...}}");
+            }
+            seen.push(ptr);
+            write!(f, "{{")?;
+            for (i, (key, value)) in map.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_element(f, &Value::Literal(key.clone()), seen)?;
+                write!(f, ": ")?;
+                write_element(f, value, seen)?;
+            }
+            seen.pop();
+            write!(f, "}}")
+        }
+    }
+}
+
+/// 容器中的字符串带上引号，便于和其他值区分
+fn write_element(
+    f: &mut fmt::Formatter<'_>,
+    value: &Value,
+    seen: &mut Vec<*const ()>,
+) -> fmt::Result {
+    match value {
+        Value::Literal(Literal::String(s)) => write!(f, "\"{}\"", s),
+        value => write_value(f, value, seen),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Environment {
     enclosing: Option<Rc<RefCell<Environment>>>,
@@ -60,6 +212,17 @@ impl Environment {
         })
     }
 
+    /// 当前作用域中定义的所有绑定，按名称排序，不包含外层作用域
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings: Vec<_> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
     pub fn get_at(&self, distance: usize, name: &str) -> Option<Value> {
         if distance == 0 {
             return self.values.get(name).cloned();
@@ -82,15 +245,18 @@ impl Environment {
         }
     }
 
-    pub fn assign(&mut self, name: String, value: Value) -> Result<(), Error> {
-        if self.values.contains_key(&name) {
-            self.values.insert(name, value);
-            Ok(())
-        } else {
-            match self.enclosing {
-                Some(ref parent) => parent.borrow_mut().assign(name, value),
-                None => Err(Error::RuntimeError(format!("Undefined variable {name}"))),
-            }
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), Error> {
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
+            return Ok(());
+        }
+        match self.enclosing {
+            Some(ref parent) => parent.borrow_mut().assign(name, value),
+            None => Err(Error::RuntimeError(
+                RuntimeErrorKind::Name,
+                name.clone(),
+                format!("Undefined variable '{}'.", name.lexeme),
+            )),
         }
     }
 }
@@ -3,29 +3,130 @@ use std::{
     fmt::{self, Display},
 };
 
-use crate::{environment::Value, lex::Token};
+use crate::lex::{Span, Token};
+
+/// 调用栈中的一帧：函数名以及该函数中正在执行的行号
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    pub line: usize,
+}
+
+impl StackFrame {
+    pub fn new(function: impl Into<String>, line: usize) -> Self {
+        Self {
+            function: function.into(),
+            line,
+        }
+    }
+}
+
+/// 运行时错误的分类，每一类对应一个稳定的错误码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+    /// 操作数或值的类型不符合要求
+    Type,
+    /// 变量未定义
+    Name,
+    /// 调用时参数个数不匹配
+    Arity,
+    /// 访问不存在的属性或在非实例上访问属性
+    Property,
+    /// 调用不可调用的值
+    Call,
+    /// 下标越界
+    Index,
+    /// 映射中不存在的键
+    Key,
+}
+
+impl RuntimeErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Type => "E0010",
+            Self::Name => "E0011",
+            Self::Arity => "E0012",
+            Self::Property => "E0013",
+            Self::Call => "E0014",
+            Self::Index => "E0015",
+            Self::Key => "E0016",
+        }
+    }
+}
+
+impl Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Type => write!(f, "TypeError"),
+            Self::Name => write!(f, "NameError"),
+            Self::Arity => write!(f, "ArityError"),
+            Self::Property => write!(f, "PropertyError"),
+            Self::Call => write!(f, "CallError"),
+            Self::Index => write!(f, "IndexError"),
+            Self::Key => write!(f, "KeyError"),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum Error {
     InternalError(String),
+    LexError(Span, String),
     ParseError(Token, String),
-    AssignmentError(String),
-    RuntimeError(String),
-    ReturnValue(Value),
+    ResolveError(Token, String),
+    RuntimeError(RuntimeErrorKind, Token, String),
+    // 内置函数或宿主程序调用产生的错误，不知道调用位置，脚本中的调用处会将其转换为 RuntimeError
+    NativeError(RuntimeErrorKind, String),
+    // 从函数调用中逃逸出来的错误，附带发生错误时的调用栈，最内层在前
+    Traced(Box<Error>, Vec<StackFrame>),
+}
+
+impl Error {
+    /// 错误发生的行号，没有位置信息时返回 None
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Self::LexError(span, _) => Some(span.line),
+            Self::ParseError(token, _)
+            | Self::ResolveError(token, _)
+            | Self::RuntimeError(_, token, _) => Some(token.span.line),
+            Self::Traced(error, _) => error.line(),
+            _ => None,
+        }
+    }
+
+    /// 进程退出码：词法、语法和静态分析错误为 65，运行时错误为 70
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::LexError(..) | Self::ParseError(..) | Self::ResolveError(..) => 65,
+            Self::Traced(error, _) => error.exit_code(),
+            Self::InternalError(_) | Self::RuntimeError(..) | Self::NativeError(..) => 70,
+        }
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::InternalError(msg) => write!(f, "{}", msg),
-            Self::ParseError(token, msg) => write!(
+            Self::LexError(span, msg) => write!(f, "[line {}] Error: {}", span.line, msg),
+            Self::ParseError(token, msg) | Self::ResolveError(token, msg) => write!(
+                f,
+                "[line {}:{}] [lexeme {}] {}",
+                token.span.line, token.span.column, token.lexeme, msg
+            ),
+            Self::RuntimeError(kind, token, msg) => write!(
                 f,
-                "[line {}] [lexeme {}] {}",
-                token.line_number, token.lexeme, msg
+                "[line {}:{}] {}: {}",
+                token.span.line, token.span.column, kind, msg
             ),
-            Self::AssignmentError(msg) => write!(f, "{}", msg),
-            Self::RuntimeError(msg) => write!(f, "{}", msg),
-            Self::ReturnValue(value) => write!(f, "{}", value.to_string()),
+            Self::NativeError(kind, msg) => write!(f, "{}: {}", kind, msg),
+            Self::Traced(error, trace) => {
+                write!(f, "{}", error)?;
+                for frame in trace {
+                    write!(f, "\n    at {} (line {})", frame.function, frame.line)?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -1,47 +1,66 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, rc::Rc};
 
-use lox_macro::New;
+use lox_macro::{ast, New};
 
-use crate::lex::{Literal as LiteralValue, Token};
+use crate::{
+    lex::{Literal as LiteralValue, Span, Token},
+    stmt::FunctionDecl,
+};
 
-pub trait ExprVisitor {
-    type Output;
-    fn visit_binary(&mut self, expr: &Binary) -> Self::Output;
-    fn visit_grouping(&mut self, expr: &Grouping) -> Self::Output;
-    fn visit_literal(&mut self, expr: &Literal) -> Self::Output;
-    fn visit_unary(&mut self, expr: &Unary) -> Self::Output;
-    fn visit_variable(&mut self, expr: &Variable) -> Self::Output;
-    fn visit_assignment(&mut self, expr: &Assignment) -> Self::Output;
-    fn visit_logical(&mut self, expr: &Logical) -> Self::Output;
-    fn visit_call(&mut self, expr: &Call) -> Self::Output;
-}
 pub trait Expr: Debug {
     fn accept<R>(&self, visitor: &mut dyn ExprVisitor<Output = R>) -> R;
 }
 
+/// 所有表达式节点，新增节点时在这里加一行并定义同名结构体
+#[ast(visitor = ExprVisitor, accept = Expr)]
 #[derive(Debug, Clone)]
 pub enum ExprEnum {
-    Binary(Binary),
-    Grouping(Grouping),
-    Literal(Literal),
-    Unary(Unary),
-    Variable(Variable),
-    Assignment(Assignment),
-    Logical(Logical),
-    Call(Call),
-}
-
-impl Expr for ExprEnum {
-    fn accept<R>(&self, visitor: &mut dyn ExprVisitor<Output = R>) -> R {
+    Binary,
+    Grouping,
+    Literal,
+    Unary,
+    Variable,
+    Assignment,
+    Logical,
+    Call,
+    Get,
+    Set,
+    This,
+    Super,
+    List,
+    Map,
+    Index,
+    IndexSet,
+    Lambda,
+    Interpolation,
+}
+
+impl ExprEnum {
+    /// 表达式在源码中覆盖的区间
+    pub fn span(&self) -> Span {
         match self {
-            ExprEnum::Binary(expr) => visitor.visit_binary(expr),
-            ExprEnum::Grouping(expr) => visitor.visit_grouping(expr),
-            ExprEnum::Literal(expr) => visitor.visit_literal(expr),
-            ExprEnum::Unary(expr) => visitor.visit_unary(expr),
-            ExprEnum::Variable(expr) => visitor.visit_variable(expr),
-            ExprEnum::Assignment(expr) => visitor.visit_assignment(expr),
-            ExprEnum::Logical(expr) => visitor.visit_logical(expr),
-            ExprEnum::Call(expr) => visitor.visit_call(expr),
+            ExprEnum::Binary(expr) => expr.left.span().merge(&expr.right.span()),
+            ExprEnum::Grouping(expr) => expr.span,
+            ExprEnum::Literal(expr) => expr.span,
+            ExprEnum::Unary(expr) => expr.operator.span.merge(&expr.right.span()),
+            ExprEnum::Variable(expr) => expr.name.span,
+            ExprEnum::Assignment(expr) => expr.name.span.merge(&expr.value.span()),
+            ExprEnum::Logical(expr) => expr.left.span().merge(&expr.right.span()),
+            ExprEnum::Call(expr) => expr.callee.span().merge(&expr.paren.span),
+            ExprEnum::Get(expr) => expr.object.span().merge(&expr.name.span),
+            ExprEnum::Set(expr) => expr.object.span().merge(&expr.value.span()),
+            ExprEnum::This(expr) => expr.keyword.span,
+            ExprEnum::Super(expr) => expr.keyword.span.merge(&expr.method.span),
+            ExprEnum::List(expr) => expr.span,
+            ExprEnum::Map(expr) => expr.span,
+            ExprEnum::Index(expr) => expr.object.span().merge(&expr.bracket.span),
+            ExprEnum::IndexSet(expr) => expr.object.span().merge(&expr.value.span()),
+            ExprEnum::Interpolation(expr) => expr.span,
+            ExprEnum::Lambda(expr) => expr
+                .declaration
+                .name
+                .span
+                .merge(&expr.declaration.body.span),
         }
     }
 }
@@ -62,11 +81,13 @@ pub struct Binary {
 #[derive(New, Debug, Clone)]
 pub struct Grouping {
     pub expression: Box<ExprEnum>,
+    pub span: Span, // 包含左右括号
 }
 
 #[derive(New, Debug, Clone)]
 pub struct Literal {
     pub value: LiteralValue,
+    pub span: Span,
 }
 
 #[derive(New, Debug, Clone)]
@@ -93,3 +114,68 @@ pub struct Call {
     pub paren: Token, // 保存右括号标记，用于错误信息展示
     pub arguments: Vec<ExprEnum>,
 }
+
+#[derive(New, Debug, Clone)]
+pub struct Get {
+    pub object: Box<ExprEnum>,
+    pub name: Token,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Set {
+    pub object: Box<ExprEnum>,
+    pub name: Token,
+    pub value: Box<ExprEnum>,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct This {
+    pub keyword: Token,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Super {
+    pub keyword: Token,
+    pub method: Token,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct List {
+    pub elements: Vec<ExprEnum>,
+    pub span: Span, // 包含左右方括号
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Map {
+    // 键、冒号和值，冒号用于定位键的错误
+    pub entries: Vec<(ExprEnum, Token, ExprEnum)>,
+    pub span: Span, // 包含左右花括号
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Index {
+    pub object: Box<ExprEnum>,
+    pub bracket: Token, // 保存右方括号标记，用于错误信息展示
+    pub index: Box<ExprEnum>,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct IndexSet {
+    pub object: Box<ExprEnum>,
+    pub bracket: Token,
+    pub index: Box<ExprEnum>,
+    pub value: Box<ExprEnum>,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Lambda {
+    // 匿名函数的名称 token 是 fun 关键字的位置，名称固定为 anonymous
+    pub declaration: Rc<FunctionDecl>,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Interpolation {
+    // 字符串片段和插值表达式按出现顺序排列，空的字符串片段会被省略
+    pub parts: Vec<ExprEnum>,
+    pub span: Span, // 包含两端的引号
+}
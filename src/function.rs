@@ -1,6 +1,12 @@
-use std::{cell::RefCell, fmt::Debug, rc::Rc};
+use std::{
+    cell::RefCell,
+    fmt::{self, Debug, Display},
+    rc::Rc,
+};
 
 use crate::{
+    class::Class,
+    control_flow::ControlFlow,
     environment::{Environment, Value},
     error::Error,
     interpreter::Interpreter,
@@ -13,10 +19,41 @@ use lox_macro::New;
 pub enum Callable {
     Function(Function),
     NativeFunction(NativeFunction),
+    Class(Rc<Class>),
+}
+
+/// 可调用对象接受的参数个数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Fixed(usize),
+    // 参数个数在闭区间 [min, max] 内，多出的形参使用默认值
+    Range(usize, usize),
+    // 至少需要给定个数的参数，多出的参数个数不限
+    Variadic(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Arity::Fixed(n) => count == *n,
+            Arity::Range(min, max) => (*min..=*max).contains(&count),
+            Arity::Variadic(min) => count >= *min,
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Fixed(n) => write!(f, "{}", n),
+            Arity::Range(min, max) => write!(f, "{} to {}", min, max),
+            Arity::Variadic(min) => write!(f, "at least {}", min),
+        }
+    }
 }
 
-pub trait CallableInterface: ToString {
-    fn arity(&self) -> usize;
+pub trait CallableInterface: Display {
+    fn arity(&self) -> Arity;
     fn call(
         &self,
         interpreter: &mut Interpreter,
@@ -25,11 +62,37 @@ pub trait CallableInterface: ToString {
     ) -> Result<Value, Error>;
 }
 
+impl Callable {
+    /// 按引用比较：函数比较声明，内置函数比较实现，类比较类对象本身
+    pub fn ptr_eq(&self, other: &Callable) -> bool {
+        match (self, other) {
+            (Callable::Function(a), Callable::Function(b)) => {
+                Rc::ptr_eq(&a.declaration, &b.declaration)
+            }
+            (Callable::NativeFunction(a), Callable::NativeFunction(b)) => {
+                Rc::ptr_eq(&a.func, &b.func)
+            }
+            (Callable::Class(a), Callable::Class(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// 用于调用栈展示的名称
+    pub fn name(&self) -> String {
+        match self {
+            Callable::Function(func) => func.declaration.name.lexeme.clone(),
+            Callable::NativeFunction(func) => func.name.clone(),
+            Callable::Class(class) => class.name.clone(),
+        }
+    }
+}
+
 impl CallableInterface for Callable {
-    fn arity(&self) -> usize {
+    fn arity(&self) -> Arity {
         match self {
             Callable::Function(func) => func.arity(),
             Callable::NativeFunction(func) => func.arity,
+            Callable::Class(class) => class.arity(),
         }
     }
 
@@ -42,27 +105,53 @@ impl CallableInterface for Callable {
         match self {
             Callable::Function(func) => func.call(interpreter, env, arguments),
             Callable::NativeFunction(func) => func.call(interpreter, env, arguments),
+            Callable::Class(class) => Class::instantiate(class, interpreter, arguments),
         }
     }
 }
 
-impl ToString for Callable {
-    fn to_string(&self) -> String {
+impl Display for Callable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Callable::Function(func) => func.to_string(),
-            Callable::NativeFunction(func) => func.to_string(),
+            Callable::Function(func) => write!(f, "{}", func),
+            Callable::NativeFunction(func) => write!(f, "{}", func),
+            Callable::Class(class) => write!(f, "{}", class),
         }
     }
 }
 
 #[derive(Debug, New, Clone)]
 pub struct Function {
-    declaration: FunctionDecl,
+    declaration: Rc<FunctionDecl>,
+    is_initializer: bool,
+}
+
+impl Function {
+    /// 创建一个定义了 this 的新环境，作为方法的闭包环境
+    pub fn bind(
+        &self,
+        instance: Value,
+        closure: Rc<RefCell<Environment>>,
+    ) -> Rc<RefCell<Environment>> {
+        let mut env = Environment::new(Some(closure));
+        env.define("this".to_string(), instance);
+        Rc::new(RefCell::new(env))
+    }
+
+    pub fn into_value(self, closure: Rc<RefCell<Environment>>) -> Value {
+        Value::Callable(Callable::Function(self), closure)
+    }
 }
 
 impl CallableInterface for Function {
-    fn arity(&self) -> usize {
-        self.declaration.parameters.len()
+    fn arity(&self) -> Arity {
+        let parameters = &self.declaration.parameters;
+        let required = parameters.iter().filter(|p| p.default.is_none()).count();
+        match self.declaration.rest {
+            Some(_) => Arity::Variadic(required),
+            None if required == parameters.len() => Arity::Fixed(required),
+            None => Arity::Range(required, parameters.len()),
+        }
     }
 
     fn call(
@@ -71,51 +160,86 @@ impl CallableInterface for Function {
         closure_env: Rc<RefCell<Environment>>,
         arguments: Vec<Value>,
     ) -> Result<Value, Error> {
-        let mut env = Environment::new(Some(closure_env));
-        for (param, argument) in self.declaration.parameters.iter().zip(arguments) {
-            env.define(param.lexeme.clone(), argument);
+        let env = Rc::new(RefCell::new(Environment::new(Some(Rc::clone(
+            &closure_env,
+        )))));
+        let mut arguments = arguments.into_iter();
+        for param in &self.declaration.parameters {
+            // 缺少的实参用默认值补齐，默认值每次调用都重新求值
+            let value = match (arguments.next(), &param.default) {
+                (Some(argument), _) => argument,
+                (None, Some(default)) => interpreter.evaluate_in(default, Rc::clone(&env))?,
+                (None, None) => {
+                    return Err(Error::InternalError(format!(
+                        "Missing argument for '{}'",
+                        param.name.lexeme
+                    )))
+                }
+            };
+            env.borrow_mut().define(param.name.lexeme.clone(), value);
+        }
+        if let Some(rest) = &self.declaration.rest {
+            let rest_values = Value::List(Rc::new(RefCell::new(arguments.collect())));
+            env.borrow_mut().define(rest.lexeme.clone(), rest_values);
         }
-        let result = interpreter.execute_block(&self.declaration.body, env);
-        match result {
-            Ok(_) => Ok(Value::Literal(Literal::Nil)),
-            Err(e) => match e {
-                Error::ReturnValue(value) => Ok(value.clone()),
-                _ => Err(e),
-            },
+        let value = match interpreter.execute_block(&self.declaration.body, env)? {
+            ControlFlow::Normal => Value::Literal(Literal::Nil),
+            ControlFlow::Return(value) => value,
+            signal => return Err(Interpreter::escaped(signal)),
+        };
+        // init 总是返回实例本身，即使是在 init 中提前 return
+        if self.is_initializer {
+            return closure_env
+                .borrow()
+                .get_at(0, "this")
+                .ok_or_else(|| Error::InternalError("'this' is not bound in initializer".into()));
         }
+        Ok(value)
     }
 }
 
-impl ToString for Function {
-    fn to_string(&self) -> String {
-        format!("<fn {}>", self.declaration.name.lexeme)
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.declaration.name.lexeme)
     }
 }
 
-#[derive(Debug, New, Clone)]
+/// 内置函数的实现，可以访问解释器，也可以捕获宿主程序的状态
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, Error>;
+
+#[derive(New, Clone)]
 pub struct NativeFunction {
     pub name: String,
-    pub arity: usize,
-    pub func: fn(Vec<Value>) -> Result<Value, Error>,
+    pub arity: Arity,
+    pub func: Rc<NativeFn>,
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NativeFunction")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive()
+    }
 }
 
 impl CallableInterface for NativeFunction {
-    fn arity(&self) -> usize {
+    fn arity(&self) -> Arity {
         self.arity
     }
 
     fn call(
         &self,
-        _interpreter: &mut Interpreter,
+        interpreter: &mut Interpreter,
         _closure_env: Rc<RefCell<Environment>>,
         arguments: Vec<Value>,
     ) -> Result<Value, Error> {
-        (self.func)(arguments)
+        (self.func)(interpreter, arguments)
     }
 }
 
-impl ToString for NativeFunction {
-    fn to_string(&self) -> String {
-        format!("<native fn {}>", self.name)
+impl Display for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
     }
 }
@@ -1,29 +1,44 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
+use indexmap::IndexMap;
+
 use crate::{
+    class::{Class as LoxClass, Instance},
+    control_flow::ControlFlow,
+    convert::IntoNative,
     environment::{Environment, Value},
-    error::Error,
+    error::{Error, RuntimeErrorKind, StackFrame},
     expr::{
-        Assignment, Binary, Call, Expr, ExprEnum, ExprVisitor, Grouping, Literal as ExprLiteral,
-        Logical, Unary, Variable,
+        Assignment, Binary, Call, Expr, ExprEnum, ExprVisitor, Get, Grouping, Index, IndexSet,
+        Interpolation, Lambda, List, Literal as ExprLiteral, Logical, Map, Set, Super, This, Unary,
+        Variable,
     },
-    function::{Callable, CallableInterface, Function, NativeFunction},
+    function::{Arity, Callable, CallableInterface, Function, NativeFunction},
     lex::{self, Literal, TokenType, Tokenizer},
     parser::Parser,
+    resolver::Resolver,
     stmt::{
-        Block, Expression, FunctionDecl, If, Print, Return, Stmt, StmtEnum, StmtVisitor, VarDecl,
-        While,
+        Block, Break, Class, Continue, Expression, FunctionDecl, If, Print, Return, Stmt, StmtEnum,
+        StmtVisitor, VarDecl, While,
     },
 };
 
 #[derive(Debug)]
-pub struct Interpreter<'a> {
+pub struct Interpreter {
     globals: Rc<RefCell<Environment>>,
     pub environment: Rc<RefCell<Environment>>,
-    pub locals: HashMap<&'a lex::Token, usize>,
+    pub locals: HashMap<lex::Token, usize>,
+    // 当前正在执行的函数调用，line 为调用发生的行号
+    call_stack: Vec<StackFrame>,
 }
 
-impl<'a> Interpreter<'a> {
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
     pub fn new() -> Self {
         let globals = Rc::new(RefCell::new(Environment::new(None)));
 
@@ -31,23 +46,32 @@ impl<'a> Interpreter<'a> {
             globals: Rc::clone(&globals),
             environment: Rc::clone(&globals),
             locals: HashMap::new(),
+            call_stack: Vec::new(),
         }
     }
 
-    pub fn resolve(&mut self, token: &'a lex::Token, depth: usize) {
-        self.locals.insert(token, depth);
+    pub fn globals(&self) -> Rc<RefCell<Environment>> {
+        Rc::clone(&self.globals)
+    }
+
+    pub fn resolve(&mut self, token: &lex::Token, depth: usize) {
+        self.locals.insert(token.clone(), depth);
     }
 
+    /// 解析、解析作用域并在全局环境中执行一段脚本，宿主程序用它加载插件脚本
     pub fn define_globals(&mut self, source: String) -> Result<(), Error> {
         let mut tokenizer = Tokenizer::new(source);
-        let (tokens, exit_code) = tokenizer.parse();
-        if exit_code != 0 {
-            return Err(Error::RuntimeError(
-                "Failed to parse function source".to_string(),
-            ));
+        let (tokens, errors) = tokenizer.parse();
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e);
         }
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse()?;
+        let (statements, errors) = parser.parse();
+        if let Some(e) = errors.into_iter().next() {
+            return Err(e);
+        }
+
+        Resolver::new(self).resolve_statements(&statements)?;
 
         let old_env = self.environment.clone();
         self.environment = Rc::clone(&self.globals);
@@ -56,27 +80,72 @@ impl<'a> Interpreter<'a> {
         r
     }
 
+    /// 在全局环境中定义一个内置函数，调用前解释器会按 arity 检查参数个数
     pub fn define_native_function(
         &mut self,
-        name: String,
-        func: fn(Vec<Value>) -> Result<Value, Error>,
+        name: impl Into<String>,
+        arity: Arity,
+        func: impl Fn(&mut Interpreter, Vec<Value>) -> Result<Value, Error> + 'static,
     ) {
+        self.register_native(NativeFunction::new(name.into(), arity, Rc::new(func)));
+    }
+
+    /// 用参数和返回值都是 Rust 类型的函数定义内置函数，参数会自动从 lox 值转换
+    pub fn define_native<Args>(&mut self, name: impl Into<String>, func: impl IntoNative<Args>) {
+        self.register_native(func.into_native(name));
+    }
+
+    /// 在全局环境中定义一个已经构造好的内置函数，例如 #[lox_native] 生成的函数
+    pub fn register_native(&mut self, native: NativeFunction) {
         self.globals.borrow_mut().define(
-            name.clone(),
-            Value::Callable(
-                Callable::NativeFunction(NativeFunction {
-                    name,
-                    arity: 0,
-                    func,
-                }),
-                Rc::clone(&self.globals),
-            ),
+            native.name.clone(),
+            Value::Callable(Callable::NativeFunction(native), Rc::clone(&self.globals)),
         );
     }
 
+    /// 按名称查找全局变量
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get(name)
+    }
+
+    /// 从宿主程序调用一个可调用值，和脚本中的调用一样先检查参数个数
+    pub fn call(&mut self, callee: &Value, arguments: Vec<Value>) -> Result<Value, Error> {
+        let Value::Callable(func, env) = callee else {
+            return Err(Error::NativeError(
+                RuntimeErrorKind::Call,
+                "Can only call functions and classes.".into(),
+            ));
+        };
+        if !func.arity().accepts(arguments.len()) {
+            return Err(Error::NativeError(
+                RuntimeErrorKind::Arity,
+                format!(
+                    "Expected {} arguments but got {}.",
+                    func.arity(),
+                    arguments.len()
+                ),
+            ));
+        }
+        func.call(self, Rc::clone(env), arguments)
+    }
+
+    /// 调用名为 name 的全局函数
+    pub fn call_global(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, Error> {
+        let callee = self.get_global(name).ok_or_else(|| {
+            Error::NativeError(
+                RuntimeErrorKind::Name,
+                format!("Undefined variable '{}'.", name),
+            )
+        })?;
+        self.call(&callee, arguments)
+    }
+
     pub fn interpret(&mut self, statements: &[StmtEnum]) -> Result<(), Error> {
         for stmt in statements {
-            self.execute(stmt)?;
+            let signal = self.execute(stmt)?;
+            if !signal.is_normal() {
+                return Err(Self::escaped(signal));
+            }
         }
         Ok(())
     }
@@ -85,24 +154,67 @@ impl<'a> Interpreter<'a> {
         expr.accept(self)
     }
 
-    fn execute(&mut self, stmt: &StmtEnum) -> Result<(), Error> {
+    fn execute(&mut self, stmt: &StmtEnum) -> Result<ControlFlow, Error> {
         stmt.accept(self)
     }
 
-    pub fn execute_block(&mut self, block: &Block, new_env: Environment) -> Result<(), Error> {
+    /// 依次执行块中的语句，遇到非正常的控制流信号立即停止并向外传递
+    pub fn execute_block(
+        &mut self,
+        block: &Block,
+        new_env: Rc<RefCell<Environment>>,
+    ) -> Result<ControlFlow, Error> {
         let old_env = self.environment.clone();
-        self.environment = Rc::new(RefCell::new(new_env));
-        let r = block.statements.iter().try_for_each(|s| {
-            let r = s.accept(self);
-            r
-        });
+        self.environment = new_env;
+        let mut r = Ok(ControlFlow::Normal);
+        for stmt in &block.statements {
+            r = stmt.accept(self);
+            if !matches!(r, Ok(ControlFlow::Normal)) {
+                break;
+            }
+        }
         self.environment = old_env;
         r
     }
 
+    /// 在给定环境中对表达式求值，求值结束后恢复当前环境
+    pub fn evaluate_in(
+        &mut self,
+        expr: &ExprEnum,
+        env: Rc<RefCell<Environment>>,
+    ) -> Result<Value, Error> {
+        let old_env = std::mem::replace(&mut self.environment, env);
+        let r = self.evaluate(expr);
+        self.environment = old_env;
+        r
+    }
+
+    /// 控制流信号越过了能处理它的边界（函数或脚本顶层）
+    pub fn escaped(signal: ControlFlow) -> Error {
+        Error::InternalError(format!("{:?} escaped its enclosing scope", signal))
+    }
+
+    /// 为第一次逃逸出函数调用的错误附加调用栈
+    fn trace(&self, error: Error) -> Error {
+        if matches!(error, Error::Traced(..)) {
+            return error;
+        }
+        let mut trace = Vec::with_capacity(self.call_stack.len() + 1);
+        // 每一帧展示的是该函数中正在执行的行：最内层是出错的位置，其余是调用下一层的位置
+        let mut line = error
+            .line()
+            .unwrap_or_else(|| self.call_stack.last().map_or(0, |frame| frame.line));
+        for frame in self.call_stack.iter().rev() {
+            trace.push(StackFrame::new(frame.function.clone(), line));
+            line = frame.line;
+        }
+        trace.push(StackFrame::new("<script>", line));
+        Error::Traced(Box::new(error), trace)
+    }
+
     fn lookup_variable(&self, name: &lex::Token) -> Option<Value> {
         let var_name = &name.lexeme.clone();
-        if let Some(depth) = self.locals.get(&name) {
+        if let Some(depth) = self.locals.get(name) {
             self.environment.borrow().get_at(*depth, var_name)
         } else {
             self.globals.borrow().get(var_name)
@@ -110,13 +222,23 @@ impl<'a> Interpreter<'a> {
     }
 }
 
-impl<'a> ExprVisitor for Interpreter<'a> {
+impl ExprVisitor for Interpreter {
     type Output = Result<Value, Error>;
 
     fn visit_binary(&mut self, expr: &Binary) -> Self::Output {
-        let right = self.evaluate(expr.right.as_ref())?;
         let left = self.evaluate(expr.left.as_ref())?;
 
+        // 逻辑运算短路，右操作数只在需要时求值
+        match expr.operator.token_type {
+            TokenType::And if !left.is_truthy() => {
+                return Ok(Value::Literal(Literal::Boolean(false)));
+            }
+            TokenType::Or if left.is_truthy() => return Ok(left),
+            TokenType::And | TokenType::Or => return self.evaluate(expr.right.as_ref()),
+            _ => {}
+        }
+        let right = self.evaluate(expr.right.as_ref())?;
+
         match expr.operator.token_type {
             TokenType::Plus => match (left, right) {
                 (Value::Literal(Literal::Number(left)), Value::Literal(Literal::Number(right))) => {
@@ -125,7 +247,8 @@ impl<'a> ExprVisitor for Interpreter<'a> {
                 (Value::Literal(Literal::String(left)), Value::Literal(Literal::String(right))) => {
                     Ok(Value::Literal(Literal::String(left + &right)))
                 }
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
                     "Operand must be two numbers or two strings.".into(),
                 )),
@@ -134,16 +257,18 @@ impl<'a> ExprVisitor for Interpreter<'a> {
                 (Value::Literal(Literal::Number(left)), Value::Literal(Literal::Number(right))) => {
                     Ok(Value::Literal(Literal::Number(left - right)))
                 }
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
-                    "Operand must be a numbers.".into(),
+                    "Operand must be numbers.".into(),
                 )),
             },
             TokenType::Slash => match (left, right) {
                 (Value::Literal(Literal::Number(left)), Value::Literal(Literal::Number(right))) => {
                     Ok(Value::Literal(Literal::Number(left / right)))
                 }
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
                     "Operand must be a number.".into(),
                 )),
@@ -152,7 +277,8 @@ impl<'a> ExprVisitor for Interpreter<'a> {
                 (Value::Literal(Literal::Number(left)), Value::Literal(Literal::Number(right))) => {
                     Ok(Value::Literal(Literal::Number(left * right)))
                 }
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
                     "Operand must be a number.".into(),
                 )),
@@ -161,7 +287,8 @@ impl<'a> ExprVisitor for Interpreter<'a> {
                 (Value::Literal(Literal::Number(left)), Value::Literal(Literal::Number(right))) => {
                     Ok(Value::Literal(Literal::Boolean(left > right)))
                 }
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
                     "Operand must be numbers.".into(),
                 )),
@@ -170,7 +297,8 @@ impl<'a> ExprVisitor for Interpreter<'a> {
                 (Value::Literal(Literal::Number(left)), Value::Literal(Literal::Number(right))) => {
                     Ok(Value::Literal(Literal::Boolean(left >= right)))
                 }
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
                     "Operand must be numbers.".into(),
                 )),
@@ -179,7 +307,8 @@ impl<'a> ExprVisitor for Interpreter<'a> {
                 (Value::Literal(Literal::Number(left)), Value::Literal(Literal::Number(right))) => {
                     Ok(Value::Literal(Literal::Boolean(left < right)))
                 }
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
                     "Operand must be numbers.".into(),
                 )),
@@ -188,56 +317,16 @@ impl<'a> ExprVisitor for Interpreter<'a> {
                 (Value::Literal(Literal::Number(left)), Value::Literal(Literal::Number(right))) => {
                     Ok(Value::Literal(Literal::Boolean(left <= right)))
                 }
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
                     "Operand must be numbers.".into(),
                 )),
             },
-            TokenType::EqualEqual => match (left, right) {
-                (Value::Literal(left), Value::Literal(right)) => {
-                    Ok(Value::Literal(Literal::Boolean(left.is_equal(&right))))
-                }
-                _ => Err(Error::ParseError(
-                    expr.operator.clone(),
-                    "Operand must be two values.".into(),
-                )),
-            },
-            TokenType::BangEqual => match (left, right) {
-                (Value::Literal(left), Value::Literal(right)) => {
-                    Ok(Value::Literal(Literal::Boolean(!left.is_equal(&right))))
-                }
-                _ => Err(Error::ParseError(
-                    expr.operator.clone(),
-                    "Operand must be two values.".into(),
-                )),
-            },
-            TokenType::And => match left {
-                Value::Literal(left) => {
-                    if !left.is_truthy() {
-                        Ok(Value::Literal(Literal::Boolean(false)))
-                    } else {
-                        self.evaluate(expr.right.as_ref())
-                    }
-                }
-                _ => Err(Error::ParseError(
-                    expr.operator.clone(),
-                    "Operand must be a boolean.".into(),
-                )),
-            },
-            TokenType::Or => match &left {
-                Value::Literal(l) => {
-                    if l.is_truthy() {
-                        Ok(left)
-                    } else {
-                        self.evaluate(expr.right.as_ref())
-                    }
-                }
-                _ => Err(Error::ParseError(
-                    expr.operator.clone(),
-                    "Operand must be a boolean.".into(),
-                )),
-            },
-            _ => Err(Error::ParseError(
+            TokenType::EqualEqual => Ok(Value::Literal(Literal::Boolean(left.is_equal(&right)))),
+            TokenType::BangEqual => Ok(Value::Literal(Literal::Boolean(!left.is_equal(&right)))),
+            _ => Err(Error::RuntimeError(
+                RuntimeErrorKind::Type,
                 expr.operator.clone(),
                 "Unknown operator.".into(),
             )),
@@ -258,19 +347,15 @@ impl<'a> ExprVisitor for Interpreter<'a> {
         match expr.operator.token_type {
             TokenType::Minus => match right {
                 Value::Literal(Literal::Number(d)) => Ok(Value::Literal(Literal::Number(-d))),
-                _ => Err(Error::ParseError(
+                _ => Err(Error::RuntimeError(
+                    RuntimeErrorKind::Type,
                     expr.operator.clone(),
                     "Operand must be a number.".into(),
                 )),
             },
-            TokenType::Bang => match right {
-                Value::Literal(l) => Ok(Value::Literal(Literal::Boolean(!l.is_truthy()))),
-                _ => Err(Error::ParseError(
-                    expr.operator.clone(),
-                    "Operand must be a boolean.".into(),
-                )),
-            },
-            _ => Err(Error::ParseError(
+            TokenType::Bang => Ok(Value::Literal(Literal::Boolean(!right.is_truthy()))),
+            _ => Err(Error::RuntimeError(
+                RuntimeErrorKind::Type,
                 expr.operator.clone(),
                 "Unknown unary operator.".into(),
             )),
@@ -278,12 +363,12 @@ impl<'a> ExprVisitor for Interpreter<'a> {
     }
 
     fn visit_variable(&mut self, expr: &Variable) -> Self::Output {
-        let value = self.environment.borrow().get(&expr.name.lexeme);
-        match value {
+        match self.lookup_variable(&expr.name) {
             Some(v) => Ok(v.clone()),
-            None => Err(Error::ParseError(
+            None => Err(Error::RuntimeError(
+                RuntimeErrorKind::Name,
                 expr.name.clone(),
-                format!("Undefined variable '{}'", expr.name.lexeme),
+                format!("Undefined variable '{}'.", expr.name.lexeme),
             )),
         }
     }
@@ -291,18 +376,16 @@ impl<'a> ExprVisitor for Interpreter<'a> {
     fn visit_assignment(&mut self, expr: &Assignment) -> Self::Output {
         let name = &expr.name;
         let value = self.evaluate(&expr.value)?;
-        let distance = self.locals.get(&name);
+        let distance = self.locals.get(name);
 
         if let Some(distance) = distance {
-            self.environment
-                .borrow_mut()
-                .assign_at(*distance, name.lexeme.clone(), value.clone())
-                .map_err(|e| Error::ParseError(name.clone(), e.to_string()))?;
+            self.environment.borrow_mut().assign_at(
+                *distance,
+                name.lexeme.clone(),
+                value.clone(),
+            )?;
         } else {
-            self.globals
-                .borrow_mut()
-                .assign(name.lexeme.clone(), value.clone())
-                .map_err(|e| Error::ParseError(name.clone(), e.to_string()))?;
+            self.globals.borrow_mut().assign(name, value.clone())?;
         }
         Ok(value)
     }
@@ -310,49 +393,107 @@ impl<'a> ExprVisitor for Interpreter<'a> {
     fn visit_logical(&mut self, expr: &Logical) -> Self::Output {
         let left = self.evaluate(&expr.left)?;
         match expr.operator.token_type {
-            TokenType::Or => match left {
-                Value::Literal(left) => {
-                    if left.is_truthy() {
-                        // 短路操作
-                        Ok(Value::Literal(Literal::Boolean(true)))
-                    } else {
-                        // 返回右边的值
-                        self.evaluate(&expr.right)
-                    }
+            TokenType::Or => {
+                if left.is_truthy() {
+                    // 短路操作
+                    Ok(Value::Literal(Literal::Boolean(true)))
+                } else {
+                    // 返回右边的值
+                    self.evaluate(&expr.right)
                 }
-                _ => Err(Error::ParseError(
-                    expr.operator.clone(),
-                    "Operand must be a boolean.".into(),
-                )),
-            },
-            TokenType::And => match left {
-                Value::Literal(left) => {
-                    if !left.is_truthy() {
-                        // 短路操作
-                        Ok(Value::Literal(Literal::Boolean(false)))
-                    } else {
-                        // 返回右边的值
-                        self.evaluate(&expr.right)
-                    }
+            }
+            TokenType::And => {
+                if !left.is_truthy() {
+                    // 短路操作
+                    Ok(Value::Literal(Literal::Boolean(false)))
+                } else {
+                    // 返回右边的值
+                    self.evaluate(&expr.right)
                 }
-                _ => Err(Error::ParseError(
-                    expr.operator.clone(),
-                    "Operand must be a boolean.".into(),
-                )),
-            },
-            _ => Err(Error::ParseError(
+            }
+            _ => Err(Error::RuntimeError(
+                RuntimeErrorKind::Type,
                 expr.operator.clone(),
                 "Unknown logical operator.".into(),
             )),
         }
     }
 
+    fn visit_get(&mut self, expr: &Get) -> Self::Output {
+        match self.evaluate(&expr.object)? {
+            Value::Instance(instance) => Instance::get(&instance, &expr.name),
+            _ => Err(Error::RuntimeError(
+                RuntimeErrorKind::Type,
+                expr.name.clone(),
+                "Only instances have properties.".into(),
+            )),
+        }
+    }
+
+    fn visit_set(&mut self, expr: &Set) -> Self::Output {
+        match self.evaluate(&expr.object)? {
+            Value::Instance(instance) => {
+                let value = self.evaluate(&expr.value)?;
+                instance.borrow_mut().set(&expr.name, value.clone());
+                Ok(value)
+            }
+            _ => Err(Error::RuntimeError(
+                RuntimeErrorKind::Property,
+                expr.name.clone(),
+                "Only instances have fields.".into(),
+            )),
+        }
+    }
+
+    fn visit_this(&mut self, expr: &This) -> Self::Output {
+        self.lookup_variable(&expr.keyword).ok_or_else(|| {
+            Error::RuntimeError(
+                RuntimeErrorKind::Name,
+                expr.keyword.clone(),
+                "Can't use 'this' outside of a class.".into(),
+            )
+        })
+    }
+
+    fn visit_super(&mut self, expr: &Super) -> Self::Output {
+        let distance = *self.locals.get(&expr.keyword).ok_or_else(|| {
+            Error::RuntimeError(
+                RuntimeErrorKind::Name,
+                expr.keyword.clone(),
+                "Can't use 'super' outside of a class.".into(),
+            )
+        })?;
+        let env = self.environment.borrow();
+        let superclass = match env.get_at(distance, "super") {
+            Some(Value::Callable(Callable::Class(class), _)) => class,
+            _ => {
+                return Err(Error::InternalError(
+                    "'super' is not bound to a class".into(),
+                ))
+            }
+        };
+        // this 总是在 super 所在环境的内一层
+        let object = env
+            .get_at(distance - 1, "this")
+            .ok_or_else(|| Error::InternalError("'this' is not bound in method".into()))?;
+        superclass
+            .bind_method(&expr.method.lexeme, object)
+            .ok_or_else(|| {
+                Error::RuntimeError(
+                    RuntimeErrorKind::Property,
+                    expr.method.clone(),
+                    format!("Undefined property '{}'.", expr.method.lexeme),
+                )
+            })
+    }
+
     fn visit_call(&mut self, expr: &Call) -> Self::Output {
         let callee = self.evaluate(expr.callee.as_ref())?;
 
         if let Value::Callable(func, env) = callee {
-            if func.arity() != expr.arguments.len() {
-                return Err(Error::ParseError(
+            if !func.arity().accepts(expr.arguments.len()) {
+                return Err(Error::RuntimeError(
+                    RuntimeErrorKind::Arity,
                     expr.paren.clone(),
                     format!(
                         "Expected {} arguments but got {}.",
@@ -366,28 +507,158 @@ impl<'a> ExprVisitor for Interpreter<'a> {
                 .iter()
                 .map(|e| self.evaluate(e))
                 .collect::<Result<Vec<_>, Error>>()?;
-            func.call(self, env, arguments)
+
+            self.call_stack
+                .push(StackFrame::new(func.name(), expr.paren.span.line));
+            let result = func
+                .call(self, env, arguments)
+                .map_err(|e| match e {
+                    // 内置函数的错误定位到调用处
+                    Error::NativeError(kind, msg) => {
+                        Error::RuntimeError(kind, expr.paren.clone(), msg)
+                    }
+                    e => e,
+                })
+                .map_err(|e| self.trace(e));
+            self.call_stack.pop();
+            result
         } else {
-            Err(Error::ParseError(
+            Err(Error::RuntimeError(
+                RuntimeErrorKind::Call,
                 expr.paren.clone(),
                 "Can only call functions and classes.".into(),
             ))
         }
     }
+
+    fn visit_list(&mut self, expr: &List) -> Self::Output {
+        let elements = expr
+            .elements
+            .iter()
+            .map(|e| self.evaluate(e))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Value::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_map(&mut self, expr: &Map) -> Self::Output {
+        let mut map = IndexMap::with_capacity(expr.entries.len());
+        for (key, colon, value) in &expr.entries {
+            let key = map_key(&self.evaluate(key)?, colon)?;
+            map.insert(key, self.evaluate(value)?);
+        }
+        Ok(Value::Map(Rc::new(RefCell::new(map))))
+    }
+
+    fn visit_index(&mut self, expr: &Index) -> Self::Output {
+        let object = self.evaluate(&expr.object)?;
+        let index = self.evaluate(&expr.index)?;
+        match object {
+            Value::List(list) => {
+                let list = list.borrow();
+                let i = list_index(&index, list.len(), &expr.bracket)?;
+                Ok(list[i].clone())
+            }
+            Value::Map(map) => {
+                let key = map_key(&index, &expr.bracket)?;
+                map.borrow().get(&key).cloned().ok_or_else(|| {
+                    Error::RuntimeError(
+                        RuntimeErrorKind::Key,
+                        expr.bracket.clone(),
+                        format!("Key {} not found.", Value::Literal(key)),
+                    )
+                })
+            }
+            _ => Err(Error::RuntimeError(
+                RuntimeErrorKind::Type,
+                expr.bracket.clone(),
+                "Only lists and maps can be indexed.".into(),
+            )),
+        }
+    }
+
+    fn visit_index_set(&mut self, expr: &IndexSet) -> Self::Output {
+        let object = self.evaluate(&expr.object)?;
+        let index = self.evaluate(&expr.index)?;
+        let value = self.evaluate(&expr.value)?;
+        match object {
+            Value::List(list) => {
+                let mut list = list.borrow_mut();
+                let i = list_index(&index, list.len(), &expr.bracket)?;
+                list[i] = value.clone();
+                Ok(value)
+            }
+            Value::Map(map) => {
+                let key = map_key(&index, &expr.bracket)?;
+                map.borrow_mut().insert(key, value.clone());
+                Ok(value)
+            }
+            _ => Err(Error::RuntimeError(
+                RuntimeErrorKind::Type,
+                expr.bracket.clone(),
+                "Only lists and maps can be indexed.".into(),
+            )),
+        }
+    }
+
+    fn visit_lambda(&mut self, expr: &Lambda) -> Self::Output {
+        let function = Function::new(Rc::clone(&expr.declaration), false);
+        Ok(function.into_value(Rc::clone(&self.environment)))
+    }
+
+    /// 每个部分按 print 的格式转换为字符串后拼接
+    fn visit_interpolation(&mut self, expr: &Interpolation) -> Self::Output {
+        let mut string = String::new();
+        for part in &expr.parts {
+            string.push_str(&self.evaluate(part)?.to_string());
+        }
+        Ok(Value::Literal(Literal::String(string)))
+    }
+}
+
+fn map_key(key: &Value, token: &lex::Token) -> Result<Literal, Error> {
+    key.as_key().ok_or_else(|| {
+        Error::RuntimeError(
+            RuntimeErrorKind::Type,
+            token.clone(),
+            "Map keys must be strings, numbers or booleans.".into(),
+        )
+    })
 }
 
-impl<'a> StmtVisitor for Interpreter<'a> {
-    type Output = Result<(), Error>;
+/// 下标必须是落在 [0, len) 内的整数
+fn list_index(index: &Value, len: usize, bracket: &lex::Token) -> Result<usize, Error> {
+    match index {
+        Value::Literal(Literal::Number(n)) if n.fract() == 0.0 => {
+            if *n >= 0.0 && (*n as usize) < len {
+                Ok(*n as usize)
+            } else {
+                Err(Error::RuntimeError(
+                    RuntimeErrorKind::Index,
+                    bracket.clone(),
+                    format!("Index {} out of bounds for list of length {}.", n, len),
+                ))
+            }
+        }
+        _ => Err(Error::RuntimeError(
+            RuntimeErrorKind::Type,
+            bracket.clone(),
+            "List index must be an integer.".into(),
+        )),
+    }
+}
+
+impl StmtVisitor for Interpreter {
+    type Output = Result<ControlFlow, Error>;
 
     fn visit_expression(&mut self, stmt: &Expression) -> Self::Output {
         self.evaluate(stmt.expression.as_ref())?;
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
 
     fn visit_print(&mut self, stmt: &Print) -> Self::Output {
         let value = self.evaluate(stmt.expression.as_ref())?;
-        println!("{}", value.to_string());
-        Ok(())
+        println!("{}", value);
+        Ok(ControlFlow::Normal)
     }
 
     fn visit_var_decl(&mut self, stmt: &VarDecl) -> Self::Output {
@@ -410,72 +681,126 @@ impl<'a> StmtVisitor for Interpreter<'a> {
                     .define(stmt.name.lexeme.clone(), Value::Literal(Literal::Nil))
             }
         }
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
 
     fn visit_block(&mut self, stmt: &Block) -> Self::Output {
-        let new_env = {
-            let env = Environment::new(Some(self.environment.clone()));
-            env
-        };
-        self.execute_block(stmt, new_env)
+        let new_env = Environment::new(Some(self.environment.clone()));
+        self.execute_block(stmt, Rc::new(RefCell::new(new_env)))
     }
 
     fn visit_if(&mut self, stmt: &If) -> Self::Output {
         let condition = self.evaluate(stmt.condition.as_ref())?;
-        match condition {
-            Value::Literal(literal) => {
-                if literal.is_truthy() {
-                    self.execute(stmt.then_branch.as_ref())?;
-                } else if let Some(else_branch) = stmt.else_branch.as_ref() {
-                    self.execute(else_branch.as_ref())?;
-                }
-            }
-            _ => {
-                return Err(Error::InternalError(
-                    "Condition must be a literal value.".into(),
-                ));
-            }
+        if condition.is_truthy() {
+            self.execute(stmt.then_branch.as_ref())
+        } else if let Some(else_branch) = stmt.else_branch.as_ref() {
+            self.execute(else_branch.as_ref())
+        } else {
+            Ok(ControlFlow::Normal)
         }
-        Ok(())
     }
 
     fn visit_while(&mut self, stmt: &While) -> Self::Output {
-        while self
-            .evaluate(stmt.condition.as_ref())?
-            .as_literal()?
-            .is_truthy()
-        {
-            self.execute(stmt.body.as_ref())?;
+        while self.evaluate(stmt.condition.as_ref())?.is_truthy() {
+            match self.execute(stmt.body.as_ref())? {
+                ControlFlow::Normal | ControlFlow::Continue => {}
+                ControlFlow::Break => break,
+                signal => return Ok(signal),
+            }
+            if let Some(increment) = &stmt.increment {
+                self.evaluate(increment)?;
+            }
         }
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
 
     fn visit_function_decl(&mut self, stmt: &FunctionDecl) -> Self::Output {
-        let function = Function::new(stmt.clone());
+        let function = Function::new(Rc::new(stmt.clone()), false);
         self.environment.borrow_mut().define(
             stmt.name.lexeme.clone(),
             Value::Callable(Callable::Function(function), Rc::clone(&self.environment)),
         );
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
 
-    fn visit_return(&mut self, stmt: &Return) -> Result<(), Error> {
-        let value = stmt
-            .value
-            .as_ref()
-            .map(|expr| self.evaluate(expr))
-            .transpose()?;
-        match value {
-            Some(value) => Err(Error::ReturnValue(value)),
-            None => Err(Error::ReturnValue(Value::Literal(Literal::Nil))),
-        }
+    fn visit_class(&mut self, stmt: &Class) -> Self::Output {
+        let name = stmt.name.lexeme.clone();
+        let superclass = match &stmt.superclass {
+            Some(superclass) => match self.visit_variable(superclass)? {
+                Value::Callable(Callable::Class(class), _) => Some(class),
+                _ => {
+                    return Err(Error::RuntimeError(
+                        RuntimeErrorKind::Type,
+                        superclass.name.clone(),
+                        "Superclass must be a class.".into(),
+                    ))
+                }
+            },
+            None => None,
+        };
+        // 先定义类名，使得方法中可以引用类自身
+        self.environment
+            .borrow_mut()
+            .define(name.clone(), Value::Literal(Literal::Nil));
+
+        // 有父类时，方法的闭包外面再包一层定义了 super 的环境
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut env = Environment::new(Some(Rc::clone(&self.environment)));
+                env.define(
+                    "super".to_string(),
+                    Value::Callable(
+                        Callable::Class(Rc::clone(superclass)),
+                        Rc::clone(&self.environment),
+                    ),
+                );
+                Rc::new(RefCell::new(env))
+            }
+            None => Rc::clone(&self.environment),
+        };
+
+        let methods = stmt
+            .methods
+            .iter()
+            .map(|method| {
+                let is_initializer = method.name.lexeme == "init";
+                (
+                    method.name.lexeme.clone(),
+                    Function::new(Rc::new(method.clone()), is_initializer),
+                )
+            })
+            .collect();
+        let class = LoxClass::new(name.clone(), superclass, methods, closure);
+        self.environment.borrow_mut().define(
+            name,
+            Value::Callable(
+                Callable::Class(Rc::new(class)),
+                Rc::clone(&self.environment),
+            ),
+        );
+        Ok(ControlFlow::Normal)
+    }
+
+    fn visit_break(&mut self, _stmt: &Break) -> Self::Output {
+        Ok(ControlFlow::Break)
+    }
+
+    fn visit_continue(&mut self, _stmt: &Continue) -> Self::Output {
+        Ok(ControlFlow::Continue)
+    }
+
+    fn visit_return(&mut self, stmt: &Return) -> Self::Output {
+        let value = match &stmt.value {
+            Some(expr) => self.evaluate(expr)?,
+            None => Value::Literal(Literal::Nil),
+        };
+        Ok(ControlFlow::Return(value))
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{lex::Tokenizer, parser::Parser};
+    use crate::{lex::Tokenizer, natives, parser::Parser, resolver::Resolver};
 
     use super::*;
 
@@ -489,14 +814,573 @@ mod tests {
         "#;
 
         let mut tokenizer = Tokenizer::new(source.to_string());
-        let (tokens, exit_code) = tokenizer.parse();
-        assert_eq!(exit_code, 0);
+        let (tokens, errors) = tokenizer.parse();
+        assert!(errors.is_empty());
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse();
-        assert!(statements.is_ok());
+        let (statements, errors) = parser.parse();
+        assert!(errors.is_empty());
         let mut interpreter = Interpreter::new();
-        let r = interpreter.interpret(&statements.unwrap());
+        let r = interpreter.interpret(&statements);
         println!("{:?}", r);
         assert!(r.is_ok());
     }
+
+    fn run(source: &str) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        natives::define(&mut interpreter);
+        run_in(&mut interpreter, source);
+        interpreter
+    }
+
+    fn run_in(interpreter: &mut Interpreter, source: &str) {
+        let mut tokenizer = Tokenizer::new(source.to_string());
+        let (tokens, errors) = tokenizer.parse();
+        assert!(errors.is_empty());
+        let mut parser = Parser::new(tokens);
+        let (statements, errors) = parser.parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        Resolver::new(interpreter)
+            .resolve_statements(&statements)
+            .unwrap();
+        interpreter.interpret(&statements).unwrap();
+    }
+
+    #[test]
+    fn test_runtime_error_stack_trace() {
+        let source = r#"
+        fun inner(n) {
+            return n + nil;
+        }
+        fun outer(n) {
+            return inner(n);
+        }
+        outer(1);
+        "#;
+        let (tokens, _) = Tokenizer::new(source.to_string()).parse();
+        let (statements, _) = Parser::new(tokens).parse();
+        let mut interpreter = Interpreter::new();
+        Resolver::new(&mut interpreter)
+            .resolve_statements(&statements)
+            .unwrap();
+        match interpreter.interpret(&statements) {
+            Err(Error::Traced(_, trace)) => assert_eq!(
+                trace,
+                vec![
+                    StackFrame::new("inner", 3),
+                    StackFrame::new("outer", 6),
+                    StackFrame::new("<script>", 8),
+                ]
+            ),
+            r => panic!("expected traced error, got {:?}", r),
+        }
+        assert!(interpreter.call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_runtime_error_kinds() {
+        let cases = [
+            ("print 1 + nil;", RuntimeErrorKind::Type),
+            ("print missing;", RuntimeErrorKind::Name),
+            ("missing = 1;", RuntimeErrorKind::Name),
+            ("fun f(a) {} f();", RuntimeErrorKind::Arity),
+            ("fun f(a) {} f(1, 2);", RuntimeErrorKind::Arity),
+            ("fun f(a, ...b) {} f();", RuntimeErrorKind::Arity),
+            ("fun f(a, b = 1) {} f(1, 2, 3);", RuntimeErrorKind::Arity),
+            ("class A {} print A().x;", RuntimeErrorKind::Property),
+            ("\"str\"();", RuntimeErrorKind::Call),
+            ("[1, 2][2];", RuntimeErrorKind::Index),
+            ("[1, 2][0.5];", RuntimeErrorKind::Type),
+            ("nil[0] = 1;", RuntimeErrorKind::Type),
+            ("var m = {\"a\": 1}; m[\"b\"];", RuntimeErrorKind::Key),
+            ("var m = {nil: 1};", RuntimeErrorKind::Type),
+        ];
+        for (source, expected) in cases {
+            let (tokens, _) = Tokenizer::new(source.to_string()).parse();
+            let (statements, _) = Parser::new(tokens).parse();
+            let mut interpreter = Interpreter::new();
+            Resolver::new(&mut interpreter)
+                .resolve_statements(&statements)
+                .unwrap();
+            match interpreter.interpret(&statements) {
+                Err(e @ Error::RuntimeError(kind, ..)) => {
+                    assert_eq!(kind, expected, "{}", source);
+                    assert_eq!(e.exit_code(), 70);
+                    assert_eq!(e.line(), Some(1));
+                }
+                r => panic!("expected runtime error for {}, got {:?}", source, r),
+            }
+        }
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> String {
+        interpreter.globals.borrow().get(name).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_class_instance_and_methods() {
+        let interpreter = run(r#"
+        class Counter {
+            init(start) {
+                this.count = start;
+            }
+            inc() {
+                this.count = this.count + 1;
+                return this;
+            }
+        }
+        var counter = Counter(1);
+        counter.inc().inc();
+        var method = counter.inc;
+        method();
+        var count = counter.count;
+        var reinit = counter.init(10) == counter;
+        "#);
+        assert_eq!(global(&interpreter, "count"), "4");
+        assert_eq!(global(&interpreter, "reinit"), "true");
+        assert_eq!(global(&interpreter, "counter"), "Counter instance");
+    }
+
+    #[test]
+    fn test_inheritance_and_super() {
+        let interpreter = run(r#"
+        class A {
+            init(name) { this.name = name; }
+            greet() { return "A " + this.name; }
+            base() { return "base"; }
+        }
+        class B < A {
+            init(name) { super.init(name + "!"); }
+            greet() { return "B " + super.greet(); }
+        }
+        class C < B {}
+        var c = C("c");
+        var greeting = c.greet();
+        var base = c.base();
+        "#);
+        assert_eq!(global(&interpreter, "greeting"), "B A c!");
+        assert_eq!(global(&interpreter, "base"), "base");
+    }
+
+    #[test]
+    fn test_return_from_nested_loop() {
+        let interpreter = run(r#"
+        fun find(limit) {
+            for (var i = 0; i < 10; i = i + 1) {
+                while (true) {
+                    if (i == limit) return i;
+                    return nil;
+                }
+            }
+            return -1;
+        }
+        fun first() {
+            var i = 0;
+            while (true) {
+                i = i + 1;
+                if (i == 3) { return i; }
+            }
+        }
+        var found = find(0);
+        var missing = find(10);
+        var third = first();
+        "#);
+        assert_eq!(global(&interpreter, "found"), "0");
+        assert_eq!(global(&interpreter, "missing"), "nil");
+        assert_eq!(global(&interpreter, "third"), "3");
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let interpreter = run(r#"
+        var sum = 0;
+        for (var i = 0; i < 10; i = i + 1) {
+            if (i == 2) continue;
+            if (i == 5) break;
+            sum = sum + i;
+        }
+        var n = 0;
+        var odd = 0;
+        while (true) {
+            n = n + 1;
+            if (n > 6) break;
+            if (n == 2 or n == 4 or n == 6) continue;
+            odd = odd + n;
+        }
+        "#);
+        // 0 + 1 + 3 + 4，continue 之后 i 仍然递增
+        assert_eq!(global(&interpreter, "sum"), "8");
+        assert_eq!(global(&interpreter, "odd"), "9");
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        for source in [
+            "break;",
+            "if (true) continue;",
+            "while (true) { fun f() { break; } }",
+        ] {
+            let (tokens, _) = Tokenizer::new(source.to_string()).parse();
+            let (statements, errors) = Parser::new(tokens).parse();
+            assert!(errors.is_empty(), "{:?}", errors);
+            let mut interpreter = Interpreter::new();
+            let r = Resolver::new(&mut interpreter).resolve_statements(&statements);
+            assert!(matches!(r, Err(Error::ResolveError(..))), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_lists() {
+        let interpreter = run(r#"
+        var a = [1, 2, 3,];
+        var b = a;
+        b[0] = "one";
+        push(a, 4);
+        var last = pop(a);
+        var nested = [[1], []];
+        nested[1] = a[1] + a[2];
+        var length = len(a);
+        var empty = len([]);
+        var same = a == b;
+        "#);
+        assert_eq!(global(&interpreter, "a"), r#"["one", 2, 3]"#);
+        assert_eq!(global(&interpreter, "last"), "4");
+        assert_eq!(global(&interpreter, "nested"), "[[1], 5]");
+        assert_eq!(global(&interpreter, "length"), "3");
+        assert_eq!(global(&interpreter, "empty"), "0");
+        assert_eq!(global(&interpreter, "same"), "true");
+    }
+
+    #[test]
+    fn test_logical_operators_use_truthiness() {
+        let interpreter = run(r#"
+        class A {}
+        var instance = A();
+        var list = [1];
+        var a = instance and 1;
+        var b = list or "unused";
+        var c = nil or list;
+        var d = [] and fun () {};
+        "#);
+        assert_eq!(global(&interpreter, "a"), "1");
+        assert_eq!(global(&interpreter, "b"), "[1]");
+        assert_eq!(global(&interpreter, "c"), "[1]");
+        assert_eq!(global(&interpreter, "d"), "<fn anonymous>");
+    }
+
+    #[test]
+    fn test_logical_operators_short_circuit() {
+        let interpreter = run(r#"
+        var calls = 0;
+        fun f() { calls = calls + 1; return true; }
+        var a = true and f();
+        var b = false and f();
+        var c = true or f();
+        var d = false or f();
+        "#);
+        assert_eq!(global(&interpreter, "calls"), "2");
+        assert_eq!(global(&interpreter, "a"), "true");
+        assert_eq!(global(&interpreter, "b"), "false");
+        assert_eq!(global(&interpreter, "c"), "true");
+        assert_eq!(global(&interpreter, "d"), "true");
+    }
+
+    #[test]
+    fn test_callables_compare_by_identity() {
+        let interpreter = run(r#"
+        fun f() {}
+        fun g() {}
+        class A {}
+        class B {}
+        fun make() { fun inner() {} return inner; }
+        var g2 = g;
+        var same_fun = f == f and g == g2;
+        var different_fun = f == g;
+        var same_class = A == A;
+        var different_class = A == B;
+        var native = clock == clock and clock != len;
+        var closures = make() == make();
+        "#);
+        assert_eq!(global(&interpreter, "same_fun"), "true");
+        assert_eq!(global(&interpreter, "different_fun"), "false");
+        assert_eq!(global(&interpreter, "same_class"), "true");
+        assert_eq!(global(&interpreter, "different_class"), "false");
+        assert_eq!(global(&interpreter, "native"), "true");
+        // 每次调用 make 都会创建新的闭包
+        assert_eq!(global(&interpreter, "closures"), "false");
+    }
+
+    #[test]
+    fn test_print_self_referencing_containers() {
+        let interpreter = run(r#"
+        var a = [1];
+        push(a, a);
+        var m = {"self": nil};
+        m["self"] = m;
+        m["list"] = a;
+        var shared = [2];
+        var twice = [shared, shared];
+        "#);
+        assert_eq!(global(&interpreter, "a"), "[1, [...]]");
+        assert_eq!(
+            global(&interpreter, "m"),
+            r#"{"self": {...}, "list": [1, [...]]}"#
+        );
+        // 同一个容器出现多次但没有形成环时正常打印
+        assert_eq!(global(&interpreter, "twice"), "[[2], [2]]");
+    }
+
+    #[test]
+    fn test_maps() {
+        let interpreter = run(r#"
+        var m = {"a": 1, 2: "two", true: [1],};
+        var alias = m;
+        alias["a"] = m["a"] + 10;
+        m[-0] = "zero";
+        var has_zero = has(m, 0);
+        var removed = remove(m, 2);
+        var missing = remove(m, "nope");
+        var ks = keys(m);
+        var vs = values(m);
+        var total = "";
+        for (var i = 0; i < len(ks); i = i + 1) {
+            total = total + "|";
+        }
+        var empty = {};
+        { var block = 1; }
+        "#);
+        assert_eq!(
+            global(&interpreter, "m"),
+            r#"{"a": 11, true: [1], 0: "zero"}"#
+        );
+        assert_eq!(global(&interpreter, "has_zero"), "true");
+        assert_eq!(global(&interpreter, "removed"), "two");
+        assert_eq!(global(&interpreter, "missing"), "nil");
+        assert_eq!(global(&interpreter, "ks"), r#"["a", true, 0]"#);
+        assert_eq!(global(&interpreter, "vs"), r#"[11, [1], "zero"]"#);
+        assert_eq!(global(&interpreter, "total"), "|||");
+        assert_eq!(global(&interpreter, "empty"), "{}");
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let interpreter = run(r#"
+        fun apply(f, x) { return f(x); }
+        fun adder(n) {
+            return fun (x) => x + n;
+        }
+        var add2 = adder(2);
+        var four = add2(2);
+        var squared = apply(fun (x) { return x * x; }, 5);
+        var called = fun () => "now";
+        called = called();
+        var fns = [fun (a, b) => a - b];
+        var diff = fns[0](5, 3);
+        var name = add2;
+        "#);
+        assert_eq!(global(&interpreter, "four"), "4");
+        assert_eq!(global(&interpreter, "squared"), "25");
+        assert_eq!(global(&interpreter, "called"), "now");
+        assert_eq!(global(&interpreter, "diff"), "2");
+        assert_eq!(global(&interpreter, "name"), "<fn anonymous>");
+        assert!(interpreter.globals.borrow().get("anonymous").is_none());
+    }
+
+    #[test]
+    fn test_native_closures() {
+        let mut interpreter = Interpreter::new();
+        let ticks = Rc::new(std::cell::Cell::new(0));
+        let counter = Rc::clone(&ticks);
+        interpreter.define_native_function("tick", Arity::Fixed(0), move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(Value::Literal(Literal::Number(counter.get() as f64)))
+        });
+        // 通过解释器回调 Lox 函数，其余参数原样传给回调
+        interpreter.define_native_function("call", Arity::Variadic(1), |interpreter, arguments| {
+            let mut arguments = arguments.into_iter();
+            match arguments.next() {
+                Some(Value::Callable(callable, env)) => {
+                    callable.call(interpreter, env, arguments.collect())
+                }
+                _ => Err(Error::NativeError(
+                    RuntimeErrorKind::Type,
+                    "call() expects a function.".into(),
+                )),
+            }
+        });
+        run_in(
+            &mut interpreter,
+            r#"
+            tick();
+            var second = tick();
+            var sum = call(fun (a, b) => a + b, 1, 2);
+            var none = call(fun () => "none");
+            "#,
+        );
+        assert_eq!(ticks.get(), 2);
+        assert_eq!(global(&interpreter, "second"), "2");
+        assert_eq!(global(&interpreter, "sum"), "3");
+        assert_eq!(global(&interpreter, "none"), "none");
+    }
+
+    #[test]
+    fn test_variadic_functions() {
+        let interpreter = run(r#"
+        fun count(first, ...rest) {
+            return len(rest);
+        }
+        var none = count(1);
+        var some = count(1, 2, 3);
+        var all = (fun (...all) => all)(1, "a");
+        var empty = (fun (...all) => all)();
+        var biggest = max(3, 9, 2);
+        var smallest = min(4, -1);
+        "#);
+        assert_eq!(global(&interpreter, "none"), "0");
+        assert_eq!(global(&interpreter, "some"), "2");
+        assert_eq!(global(&interpreter, "all"), r#"[1, "a"]"#);
+        assert_eq!(global(&interpreter, "empty"), "[]");
+        assert_eq!(global(&interpreter, "biggest"), "9");
+        assert_eq!(global(&interpreter, "smallest"), "-1");
+    }
+
+    #[test]
+    fn test_default_parameters() {
+        let interpreter = run(r#"
+        var calls = 0;
+        fun next() {
+            calls = calls + 1;
+            return calls;
+        }
+        fun greet(name, greeting = "hi", suffix = greeting + "!") {
+            return greeting + " " + name + suffix;
+        }
+        fun fresh(list = []) {
+            push(list, 1);
+            return len(list);
+        }
+        var tagged = fun (id = next(), ...rest) => [id, len(rest)];
+        var a = greet("bob");
+        var b = greet("bob", "yo");
+        var c = greet("bob", "yo", "?");
+        var d = fresh() + fresh();
+        var e = tagged();
+        var f = tagged(9, 1, 2);
+        var g = (fun (x = 1) => x)();
+        "#);
+        assert_eq!(global(&interpreter, "a"), "hi bobhi!");
+        assert_eq!(global(&interpreter, "b"), "yo bobyo!");
+        assert_eq!(global(&interpreter, "c"), "yo bob?");
+        // 默认值每次调用都重新求值，不会共享同一个列表
+        assert_eq!(global(&interpreter, "d"), "2");
+        assert_eq!(global(&interpreter, "e"), "[1, 0]");
+        assert_eq!(global(&interpreter, "f"), "[9, 2]");
+        assert_eq!(global(&interpreter, "calls"), "1");
+        assert_eq!(global(&interpreter, "g"), "1");
+    }
+
+    #[test]
+    fn test_embedding_multiple_scripts() {
+        let mut interpreter = Interpreter::new();
+        for source in [
+            "fun f(x) { return x; }",
+            "var x = 5;",
+            "fun h(y) { return x; }",
+        ] {
+            interpreter.define_globals(source.into()).unwrap();
+        }
+        let nil = || Value::Literal(Literal::Nil);
+        // 后加载脚本中的全局变量 x 与第一个脚本中的形参 x 位置相同，不能被当作局部变量
+        let h = interpreter.call_global("h", vec![nil()]).unwrap();
+        assert_eq!(h.as_number().unwrap(), 5.0);
+        let f = interpreter
+            .call_global("f", vec![Value::Literal(Literal::Number(1.0))])
+            .unwrap();
+        assert_eq!(f.as_number().unwrap(), 1.0);
+        // 错误位置相对于出错的脚本本身
+        let Err(Error::ParseError(token, _)) = interpreter.define_globals("var = 1;".into()) else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(token.span.start, 4);
+    }
+
+    #[test]
+    fn test_embedding() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .define_globals(
+                r#"
+                var greeting = "hello";
+                fun on_request(path, times) {
+                    var out = "";
+                    for (var i = 0; i < times; i = i + 1) out = out + path;
+                    return out + ":" + greeting;
+                }
+                class Counter {
+                    init(start) { this.count = start; }
+                }
+                "#
+                .into(),
+            )
+            .unwrap();
+
+        let greeting = interpreter.get_global("greeting").unwrap();
+        assert_eq!(greeting.as_string().unwrap(), "hello");
+        assert!(interpreter.get_global("missing").is_none());
+
+        let result = interpreter
+            .call_global(
+                "on_request",
+                vec![
+                    Value::Literal(Literal::String("/a".into())),
+                    Value::Literal(Literal::Number(2.0)),
+                ],
+            )
+            .unwrap();
+        assert_eq!(result.as_string().unwrap(), "/a/a:hello");
+
+        let counter = interpreter.get_global("Counter").unwrap();
+        let instance = interpreter
+            .call(&counter, vec![Value::Literal(Literal::Number(1.0))])
+            .unwrap();
+        assert!(matches!(instance, Value::Instance(_)));
+
+        let errors = [
+            interpreter.call_global("on_request", vec![]),
+            interpreter.call_global("missing", vec![]),
+            interpreter.call(&greeting, vec![]),
+        ];
+        let kinds: Vec<_> = errors
+            .into_iter()
+            .map(|r| match r {
+                Err(Error::NativeError(kind, _)) => kind,
+                r => panic!("expected a native error, got {:?}", r),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                RuntimeErrorKind::Arity,
+                RuntimeErrorKind::Name,
+                RuntimeErrorKind::Call
+            ]
+        );
+        assert!(matches!(
+            greeting.as_number(),
+            Err(Error::NativeError(RuntimeErrorKind::Type, _))
+        ));
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let interpreter = run(r#"
+        var name = "lox";
+        var items = [1, "two"];
+        var a = "hi ${name}, ${len(items)} items: ${items}";
+        var b = "${ {"k": "v"}["k"] }${"${name + "!"}"}";
+        var c = "${nil} ${true} ${1.5}";
+        "#);
+        assert_eq!(global(&interpreter, "a"), "hi lox, 2 items: [1, \"two\"]");
+        assert_eq!(global(&interpreter, "b"), "vlox!");
+        assert_eq!(global(&interpreter, "c"), "nil true 1.5");
+    }
 }
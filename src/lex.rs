@@ -1,7 +1,8 @@
 use std::fmt;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use log::error;
+use crate::error::Error;
 
 #[derive(PartialEq, Debug, Clone, Eq, Hash)]
 pub enum TokenType {
@@ -10,8 +11,12 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
+    Ellipsis,
     Minus,
     Plus,
     Semicolon,
@@ -23,6 +28,7 @@ pub enum TokenType {
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
@@ -31,12 +37,18 @@ pub enum TokenType {
     // Literals
     Identifier,
     String,
+    // 插值字符串中 ${ 之前的部分，最后一部分仍然是 String
+    Interpolation,
     Number,
+    // /// 开头的文档注释，由解析器附加到紧随其后的声明上
+    DocComment,
 
     // keywords
     Var,
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     For,
@@ -54,25 +66,32 @@ pub enum TokenType {
     Eof,
 }
 
-impl ToString for TokenType {
-    fn to_string(&self) -> String {
-        match self {
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
             TokenType::Var => "VAR",
             TokenType::Identifier => "IDENTIFIER",
             TokenType::Equal => "EQUAL",
             TokenType::String => "STRING",
+            TokenType::Interpolation => "INTERPOLATION",
+            TokenType::DocComment => "DOC_COMMENT",
             TokenType::Semicolon => "SEMICOLON",
             TokenType::LeftParen => "LEFT_PAREN",
             TokenType::RightParen => "RIGHT_PAREN",
             TokenType::LeftBrace => "LEFT_BRACE",
             TokenType::RightBrace => "RIGHT_BRACE",
+            TokenType::LeftBracket => "LEFT_BRACKET",
+            TokenType::RightBracket => "RIGHT_BRACKET",
             TokenType::Eof => "EOF",
             TokenType::Star => "STAR",
             TokenType::Dot => "DOT",
+            TokenType::Ellipsis => "ELLIPSIS",
             TokenType::Comma => "COMMA",
+            TokenType::Colon => "COLON",
             TokenType::Plus => "PLUS",
             TokenType::Minus => "MINUS",
             TokenType::EqualEqual => "EQUAL_EQUAL",
+            TokenType::Arrow => "ARROW",
             TokenType::Bang => "BANG",
             TokenType::BangEqual => "BANG_EQUAL",
             TokenType::Greater => "GREATER",
@@ -82,7 +101,9 @@ impl ToString for TokenType {
             TokenType::Slash => "SLASH",
             TokenType::Number => "NUMBER",
             TokenType::And => "AND",
+            TokenType::Break => "BREAK",
             TokenType::Class => "CLASS",
+            TokenType::Continue => "CONTINUE",
             TokenType::Else => "ELSE",
             TokenType::False => "FALSE",
             TokenType::For => "FOR",
@@ -96,8 +117,49 @@ impl ToString for TokenType {
             TokenType::This => "THIS",
             TokenType::True => "TRUE",
             TokenType::While => "WHILE",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 源码中的一段区间，start/end 为字节偏移（左闭右开），line/column 为起始位置，从 1 开始计数
+#[derive(Debug, Clone, Copy, Default, Hash, Eq, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    /// 合并两个区间，起始位置取靠前的一个
+    pub fn merge(&self, other: &Span) -> Span {
+        let (first, _) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        Span {
+            start: first.start,
+            end: self.end.max(other.end),
+            line: first.line,
+            column: first.column,
         }
-        .into()
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
     }
 }
 
@@ -106,7 +168,9 @@ pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<Literal>,
-    pub line_number: usize,
+    pub span: Span,
+    // 产生该 token 的 Tokenizer 编号，区分不同源码中位置相同的 token
+    pub source_id: usize,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -131,6 +195,8 @@ impl std::hash::Hash for Literal {
 }
 
 impl Literal {
+    // token 输出格式与 Display 不同：整数保留一位小数，nil 输出为 null
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         match self {
             Literal::String(s) => s.clone(),
@@ -191,20 +257,27 @@ impl Token {
         token_type: TokenType,
         lexeme: String,
         literal: Option<Literal>,
-        line_number: usize,
+        span: Span,
     ) -> Self {
         Self {
             token_type,
             lexeme,
             literal,
-            line_number,
+            span,
+            source_id: 0,
         }
     }
 
-    fn from_str(s: &str) -> Option<Token> {
-        let token_type = match s {
+    pub fn line_number(&self) -> usize {
+        self.span.line
+    }
+
+    fn keyword(s: &str) -> Option<TokenType> {
+        match s {
             "and" => Some(TokenType::And),
+            "break" => Some(TokenType::Break),
             "class" => Some(TokenType::Class),
+            "continue" => Some(TokenType::Continue),
             "else" => Some(TokenType::Else),
             "false" => Some(TokenType::False),
             "for" => Some(TokenType::For),
@@ -220,8 +293,7 @@ impl Token {
             "var" => Some(TokenType::Var),
             "while" => Some(TokenType::While),
             _ => None,
-        };
-        token_type.map_or(None, |t| Some(Token::new(t, s.to_string(), None, 0)))
+        }
     }
 }
 
@@ -230,37 +302,102 @@ impl fmt::Display for Token {
         write!(
             f,
             "{} {} {}",
-            self.token_type.to_string(),
+            self.token_type,
             self.lexeme,
             self.literal.as_ref().unwrap_or(&Literal::Nil).to_string(),
         )
     }
 }
 
+/// 所有关键字，用于 REPL 的自动补全
+pub const KEYWORDS: &[&str] = &[
+    "and", "break", "class", "continue", "else", "false", "for", "fun", "if", "nil", "or", "print",
+    "return", "super", "this", "true", "var", "while",
+];
+
 pub struct Tokenizer {
     line_number: usize,
     source: Vec<char>,
+    // 每个字符在源码中的字节偏移，最后额外保存源码的总字节数
+    offsets: Vec<usize>,
     start: usize,
     current: usize,
+    // 当前行首字符的下标，用于计算列号
+    line_start: usize,
+    // 当前 token 起始位置的行列号，多行字符串会在扫描过程中改变 line_number
+    start_line: usize,
+    start_column: usize,
+    // 尚未结束的字符串插值，保存插值表达式中未闭合的左花括号个数和插值开始的 token
+    interpolations: Vec<(usize, Span)>,
+    source_id: usize,
 }
 
+// 编号 0 留给不是由 Tokenizer 产生的 token
+static NEXT_SOURCE_ID: AtomicUsize = AtomicUsize::new(1);
+
 impl Tokenizer {
     pub fn new(source: String) -> Self {
+        let mut offsets: Vec<usize> = source.char_indices().map(|(i, _)| i).collect();
+        offsets.push(source.len());
         Self {
             source: source.chars().collect(),
+            offsets,
             start: 0,
             current: 0,
             line_number: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
+            interpolations: Vec::new(),
+            source_id: NEXT_SOURCE_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// 从给定的字节偏移和行号开始计数，用于把一段源码接在已有源码之后扫描
+    pub fn starting_at(mut self, offset: usize, line: usize) -> Self {
+        self.offsets.iter_mut().for_each(|o| *o += offset);
+        self.line_number = line;
+        self.start_line = line;
+        self
+    }
+
+    fn make_token(&self, token_type: TokenType, lexeme: String, literal: Option<Literal>) -> Token {
+        let span = Span::new(
+            self.offsets[self.start],
+            self.offsets[self.current],
+            self.start_line,
+            self.start_column,
+        );
+        Token {
+            source_id: self.source_id,
+            ..Token::new(token_type, lexeme, literal, span)
         }
     }
 
-    pub fn parse(&mut self) -> (Vec<Token>, i32) {
+    fn new_line(&mut self) {
+        self.line_number += 1;
+        self.line_start = self.current;
+    }
+
+    /// 词法错误覆盖当前正在扫描的 token
+    fn error(&self, message: impl Into<String>) -> Error {
+        let span = Span::new(
+            self.offsets[self.start],
+            self.offsets[self.current],
+            self.start_line,
+            self.start_column,
+        );
+        Error::LexError(span, message.into())
+    }
+
+    /// 扫描整个源码，返回所有 token 以及遇到的词法错误
+    pub fn parse(&mut self) -> (Vec<Token>, Vec<Error>) {
         let mut tokens = Vec::new();
-        let mut exit_code = 0;
+        let mut errors = Vec::new();
         while let Some(c) = self.advance() {
             // skip new line
             if matches!(c, '\n') {
-                self.line_number += 1;
+                self.new_line();
                 self.start = self.current;
                 continue;
             }
@@ -269,181 +406,104 @@ impl Tokenizer {
                 self.start = self.current;
                 continue;
             }
+            self.start_line = self.line_number;
+            self.start_column = self.start - self.line_start + 1;
             let token = match c {
-                '(' => Some(Token::new(
-                    TokenType::LeftParen,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
-                ')' => Some(Token::new(
-                    TokenType::RightParen,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
-                '{' => Some(Token::new(
-                    TokenType::LeftBrace,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
-                '}' => Some(Token::new(
-                    TokenType::RightBrace,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
-                '*' => Some(Token::new(
-                    TokenType::Star,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
-                '.' => Some(Token::new(TokenType::Dot, c.into(), None, self.line_number)),
-                ',' => Some(Token::new(
-                    TokenType::Comma,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
-                '+' => Some(Token::new(
-                    TokenType::Plus,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
-                '-' => Some(Token::new(
-                    TokenType::Minus,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
-                ';' => Some(Token::new(
-                    TokenType::Semicolon,
-                    c.into(),
-                    None,
-                    self.line_number,
-                )),
+                '(' => Some(self.make_token(TokenType::LeftParen, c.into(), None)),
+                ')' => Some(self.make_token(TokenType::RightParen, c.into(), None)),
+                '{' => {
+                    if let Some((depth, _)) = self.interpolations.last_mut() {
+                        *depth += 1;
+                    }
+                    Some(self.make_token(TokenType::LeftBrace, c.into(), None))
+                }
+                // 与 ${ 配对的右花括号结束插值表达式，继续扫描字符串的剩余部分
+                '}' => match self.interpolations.last_mut() {
+                    Some((0, _)) => {
+                        self.interpolations.pop();
+                        self.string(&mut errors)
+                    }
+                    Some((depth, _)) => {
+                        *depth -= 1;
+                        Some(self.make_token(TokenType::RightBrace, c.into(), None))
+                    }
+                    None => Some(self.make_token(TokenType::RightBrace, c.into(), None)),
+                },
+                '[' => Some(self.make_token(TokenType::LeftBracket, c.into(), None)),
+                ']' => Some(self.make_token(TokenType::RightBracket, c.into(), None)),
+                '*' => Some(self.make_token(TokenType::Star, c.into(), None)),
+                '.' => match (self.peek(), self.peek_next()) {
+                    (Some('.'), Some('.')) => {
+                        self.current += 2;
+                        Some(self.make_token(TokenType::Ellipsis, "...".into(), None))
+                    }
+                    _ => Some(self.make_token(TokenType::Dot, c.into(), None)),
+                },
+                ',' => Some(self.make_token(TokenType::Comma, c.into(), None)),
+                ':' => Some(self.make_token(TokenType::Colon, c.into(), None)),
+                '+' => Some(self.make_token(TokenType::Plus, c.into(), None)),
+                '-' => Some(self.make_token(TokenType::Minus, c.into(), None)),
+                ';' => Some(self.make_token(TokenType::Semicolon, c.into(), None)),
                 '=' => match self.peek() {
                     Some('=') => {
                         // 已经消费了，offset + 1
                         self.current += 1;
-                        Some(Token::new(
-                            TokenType::EqualEqual,
-                            "==".into(),
-                            None,
-                            self.line_number,
-                        ))
+                        Some(self.make_token(TokenType::EqualEqual, "==".into(), None))
                     }
-                    _ => Some(Token::new(
-                        TokenType::Equal,
-                        c.into(),
-                        None,
-                        self.line_number,
-                    )),
+                    Some('>') => {
+                        self.current += 1;
+                        Some(self.make_token(TokenType::Arrow, "=>".into(), None))
+                    }
+                    _ => Some(self.make_token(TokenType::Equal, c.into(), None)),
                 },
                 '!' => match self.peek() {
                     Some('=') => {
                         self.current += 1;
-                        Some(Token::new(
-                            TokenType::BangEqual,
-                            "!=".into(),
-                            None,
-                            self.line_number,
-                        ))
+                        Some(self.make_token(TokenType::BangEqual, "!=".into(), None))
                     }
-                    _ => Some(Token::new(
-                        TokenType::Bang,
-                        c.into(),
-                        None,
-                        self.line_number,
-                    )),
+                    _ => Some(self.make_token(TokenType::Bang, c.into(), None)),
                 },
                 '<' => match self.peek() {
                     Some('=') => {
                         self.current += 1;
-                        Some(Token::new(
-                            TokenType::LessEqual,
-                            "<=".into(),
-                            None,
-                            self.line_number,
-                        ))
+                        Some(self.make_token(TokenType::LessEqual, "<=".into(), None))
                     }
-                    _ => Some(Token::new(
-                        TokenType::Less,
-                        c.into(),
-                        None,
-                        self.line_number,
-                    )),
+                    _ => Some(self.make_token(TokenType::Less, c.into(), None)),
                 },
                 '>' => match self.peek() {
                     Some('=') => {
                         self.current += 1;
-                        Some(Token::new(
-                            TokenType::GreaterEqual,
-                            ">=".into(),
-                            None,
-                            self.line_number,
-                        ))
+                        Some(self.make_token(TokenType::GreaterEqual, ">=".into(), None))
                     }
-                    _ => Some(Token::new(
-                        TokenType::Greater,
-                        c.into(),
-                        None,
-                        self.line_number,
-                    )),
+                    _ => Some(self.make_token(TokenType::Greater, c.into(), None)),
                 },
                 '/' => match self.peek() {
                     Some('/') => {
                         self.current += 1;
+                        // 恰好三个斜杠是文档注释，四个及以上仍然是普通注释
+                        let is_doc = self.peek() == Some('/') && self.peek_next() != Some('/');
                         while let Some(c) = self.peek() {
                             match c {
                                 '\n' => break,
                                 _ => self.current += 1,
                             }
                         }
-                        continue;
-                    }
-                    _ => Some(Token::new(
-                        TokenType::Slash,
-                        c.into(),
-                        None,
-                        self.line_number,
-                    )),
-                },
-                '"' => {
-                    let mut has_terminated = false;
-                    while let Some(c) = self.advance() {
-                        match c {
-                            '"' => {
-                                has_terminated = true;
-                                break;
-                            }
-                            '\n' => {
-                                self.line_number += 1;
-                            }
-                            _ => {
-                                continue;
-                            }
+                        if is_doc {
+                            Some(self.doc_comment())
+                        } else {
+                            continue;
                         }
                     }
-                    if !has_terminated {
-                        error!("[line {}] Error: Unterminated string.", self.line_number);
+                    Some('*') => {
+                        self.current += 1;
+                        if let Err(e) = self.block_comment() {
+                            errors.push(e);
+                        }
                         None
-                    } else {
-                        // ignore double quote
-                        let literal: String = self.source[self.start + 1..self.current - 1]
-                            .iter()
-                            .collect();
-                        Some(Token::new(
-                            TokenType::String,
-                            format!("\"{}\"", literal),
-                            Some(Literal::String(literal)),
-                            self.line_number,
-                        ))
                     }
-                }
+                    _ => Some(self.make_token(TokenType::Slash, c.into(), None)),
+                },
+                '"' => self.string(&mut errors),
                 '0'..='9' => {
                     while let Some(c) = self.peek() {
                         match c {
@@ -463,11 +523,10 @@ impl Tokenizer {
                         }
                     }
                     let literal: String = self.source[self.start..self.current].iter().collect();
-                    Some(Token::new(
+                    Some(self.make_token(
                         TokenType::Number,
                         literal.clone(),
                         Some(Literal::Number(literal.parse::<f64>().unwrap())),
-                        self.line_number,
                     ))
                 }
                 'a'..='z' | 'A'..='Z' | '_' => {
@@ -479,41 +538,166 @@ impl Tokenizer {
                         }
                     }
                     let literal: String = self.source[self.start..self.current].iter().collect();
-                    if let Some(keyword) = Token::from_str(&literal) {
-                        Some(keyword)
+                    if let Some(keyword) = Token::keyword(&literal) {
+                        Some(self.make_token(keyword, literal, None))
                     } else {
-                        Some(Token::new(
-                            TokenType::Identifier,
-                            literal,
-                            None,
-                            self.line_number,
-                        ))
+                        Some(self.make_token(TokenType::Identifier, literal, None))
                     }
                 }
                 _ => {
-                    error!(
-                        "[line {}] Error: Unexpected character: {}",
-                        self.line_number, c
-                    );
+                    errors.push(self.error(format!("Unexpected character: {}", c)));
                     None
                 }
             };
-            match token {
-                Some(t) => tokens.push(t),
-                None => {
-                    exit_code = 65;
-                }
+            if let Some(t) = token {
+                tokens.push(t);
             }
             // update start
             self.start = self.current;
         }
-        tokens.push(Token::new(
-            TokenType::Eof,
-            "".into(),
-            None,
+        for (_, span) in self.interpolations.drain(..) {
+            errors.push(Error::LexError(
+                span,
+                "Unterminated string interpolation.".into(),
+            ));
+        }
+        self.start = self.current;
+        self.start_line = self.line_number;
+        self.start_column = self.current - self.line_start + 1;
+        tokens.push(self.make_token(TokenType::Eof, "".into(), None));
+        (tokens, errors)
+    }
+
+    /// 跳过块注释，起始的 /* 已经被消费；块注释可以嵌套，内部的换行照常计数
+    fn block_comment(&mut self) -> Result<(), Error> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance() {
+                None => return Err(self.error("Unterminated block comment.")),
+                Some('\n') => self.new_line(),
+                Some('/') if self.peek() == Some('*') => {
+                    self.current += 1;
+                    depth += 1;
+                }
+                Some('*') if self.peek() == Some('/') => {
+                    self.current += 1;
+                    depth -= 1;
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// 文档注释的内容去掉 /// 和紧随其后的一个空格
+    fn doc_comment(&self) -> Token {
+        let lexeme: String = self.source[self.start..self.current].iter().collect();
+        let text = lexeme[3..].trim_end_matches('\r');
+        let text = text.strip_prefix(' ').unwrap_or(text).to_string();
+        self.make_token(TokenType::DocComment, lexeme, Some(Literal::String(text)))
+    }
+
+    /// 扫描字符串字面量并处理转义序列，起始引号或结束插值的右花括号已经被消费
+    /// 无效的转义记录错误后继续扫描，字符串本身仍然生成 token
+    /// 遇到 ${ 时生成 Interpolation token 并回到普通扫描，由配对的右花括号继续扫描剩余部分
+    fn string(&mut self, errors: &mut Vec<Error>) -> Option<Token> {
+        let mut value = String::new();
+        loop {
+            match self.advance() {
+                None => {
+                    errors.push(self.error("Unterminated string."));
+                    return None;
+                }
+                Some('"') => break,
+                Some('$') if self.peek() == Some('{') => {
+                    self.current += 1;
+                    let lexeme = self.source[self.start..self.current].iter().collect();
+                    let token = self.make_token(
+                        TokenType::Interpolation,
+                        lexeme,
+                        Some(Literal::String(value)),
+                    );
+                    self.interpolations.push((0, token.span));
+                    return Some(token);
+                }
+                Some('\n') => {
+                    self.new_line();
+                    value.push('\n');
+                }
+                Some('\\') if !self.is_at_end() => match self.escape() {
+                    Ok(c) => value.push(c),
+                    Err(e) => errors.push(e),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+        let lexeme = self.source[self.start..self.current].iter().collect();
+        Some(self.make_token(TokenType::String, lexeme, Some(Literal::String(value))))
+    }
+
+    /// 解析反斜杠之后的转义序列，反斜杠已经被消费
+    fn escape(&mut self) -> Result<char, Error> {
+        let backslash = self.current - 1;
+        let c = match self.peek() {
+            // 不消费换行，交给外层更新行号
+            Some('\n') | None => {
+                return Err(self.error_from(backslash, "Invalid escape sequence at end of line."))
+            }
+            Some(c) => c,
+        };
+        self.current += 1;
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+            '$' => Ok('$'),
+            'u' => self.unicode_escape(backslash),
+            c => Err(self.error_from(backslash, format!("Invalid escape sequence '\\{}'.", c))),
+        }
+    }
+
+    /// 解析 \u{XXXX} 形式的转义，花括号中是 1 到 6 位十六进制数
+    fn unicode_escape(&mut self, backslash: usize) -> Result<char, Error> {
+        let malformed = "Unicode escape must be written as '\\u{...}' with 1 to 6 hex digits.";
+        if self.peek() != Some('{') {
+            return Err(self.error_from(backslash, malformed));
+        }
+        self.current += 1;
+        let digits_start = self.current;
+        while self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+            self.current += 1;
+        }
+        let digits: String = self.source[digits_start..self.current].iter().collect();
+        if self.peek() != Some('}') {
+            return Err(self.error_from(backslash, malformed));
+        }
+        self.current += 1;
+        if digits.is_empty() || digits.len() > 6 {
+            return Err(self.error_from(backslash, malformed));
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| {
+                self.error_from(
+                    backslash,
+                    format!("Invalid Unicode code point U+{}.", digits.to_uppercase()),
+                )
+            })
+    }
+
+    /// 词法错误覆盖从 start 到当前位置的字符，用于指向 token 内部的某一段
+    fn error_from(&self, start: usize, message: impl Into<String>) -> Error {
+        let span = Span::new(
+            self.offsets[start],
+            self.offsets[self.current],
             self.line_number,
-        ));
-        (tokens, exit_code)
+            start - self.line_start + 1,
+        );
+        Error::LexError(span, message.into())
     }
 
     /// is end of the source
@@ -550,3 +734,167 @@ impl Tokenizer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_spans() {
+        let source = "var s = \"é\";\n  print s;";
+        let (tokens, errors) = Tokenizer::new(source.to_string()).parse();
+        assert!(errors.is_empty());
+
+        let string = &tokens[3];
+        assert_eq!(string.token_type, TokenType::String);
+        // é 占两个字节
+        assert_eq!(string.span, Span::new(8, 12, 1, 9));
+        assert_eq!(&source[string.span.start..string.span.end], "\"é\"");
+
+        let print = &tokens[5];
+        assert_eq!(print.token_type, TokenType::Print);
+        assert_eq!(print.span, Span::new(16, 21, 2, 3));
+
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.span, Span::new(source.len(), source.len(), 2, 11));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let source = r#""a\n\t\"b\\" "\u{1F600}\u{e9}" "line
+two\r""#;
+        let (tokens, errors) = Tokenizer::new(source.to_string()).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let strings: Vec<_> = tokens
+            .iter()
+            .filter_map(|token| match &token.literal {
+                Some(Literal::String(s)) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(strings, ["a\n\t\"b\\", "😀é", "line\ntwo\r"]);
+        // lexeme 保留源码原文
+        assert_eq!(tokens[0].lexeme, r#""a\n\t\"b\\""#);
+        assert_eq!(tokens[2].span.line, 1);
+        assert_eq!(tokens[3].span.line, 2);
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        let source = "var s = \"ok\";\nprint \"a\\qb \\u{D800} \\u{} \\u41\";";
+        let (tokens, errors) = Tokenizer::new(source.to_string()).parse();
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|e| match e {
+                Error::LexError(span, msg) => (
+                    span.line,
+                    span.column,
+                    &source[span.start..span.end],
+                    msg.as_str(),
+                ),
+                e => panic!("unexpected error {:?}", e),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (2, 9, "\\q", "Invalid escape sequence '\\q'."),
+                (2, 13, "\\u{D800}", "Invalid Unicode code point U+D800."),
+                (
+                    2,
+                    22,
+                    "\\u{}",
+                    "Unicode escape must be written as '\\u{...}' with 1 to 6 hex digits."
+                ),
+                (
+                    2,
+                    27,
+                    "\\u",
+                    "Unicode escape must be written as '\\u{...}' with 1 to 6 hex digits."
+                ),
+            ]
+        );
+        // 出错后继续扫描，后面的 token 不受影响
+        assert_eq!(tokens[tokens.len() - 2].token_type, TokenType::Semicolon);
+
+        let (_, errors) = Tokenizer::new("\"abc\\".to_string()).parse();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            Error::LexError(span, msg) if span.end == 5 && msg == "Unterminated string."
+        ));
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let source = r#""a ${x} b ${ {"k": "${y}"}["k"] }\${z}""#;
+        let (tokens, errors) = Tokenizer::new(source.to_string()).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let kinds: Vec<_> = tokens.iter().map(|t| t.token_type.to_string()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "INTERPOLATION",
+                "IDENTIFIER",
+                "INTERPOLATION",
+                "LEFT_BRACE",
+                "STRING",
+                "COLON",
+                "INTERPOLATION",
+                "IDENTIFIER",
+                "STRING",
+                "RIGHT_BRACE",
+                "LEFT_BRACKET",
+                "STRING",
+                "RIGHT_BRACKET",
+                "STRING",
+                "EOF"
+            ]
+        );
+        assert_eq!(tokens[2].literal, Some(Literal::String(" b ".into())));
+        assert_eq!(tokens[13].literal, Some(Literal::String("${z}".into())));
+
+        let (_, errors) = Tokenizer::new("\"a ${ {x} ".to_string()).parse();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            Error::LexError(span, msg)
+                if span.column == 1 && msg == "Unterminated string interpolation."
+        ));
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "a /* one\n /* nested */ still\n */ b /**/c\n//// plain\n/// doc\r\n///\nd";
+        let (tokens, errors) = Tokenizer::new(source.to_string()).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+        let tokens: Vec<_> = tokens
+            .iter()
+            .map(|t| (t.token_type.to_string(), t.lexeme.clone(), t.span.line))
+            .collect();
+        let expected = [
+            ("IDENTIFIER", "a", 1),
+            ("IDENTIFIER", "b", 3),
+            ("IDENTIFIER", "c", 3),
+            ("DOC_COMMENT", "/// doc\r", 5),
+            ("DOC_COMMENT", "///", 6),
+            ("IDENTIFIER", "d", 7),
+            ("EOF", "", 7),
+        ];
+        assert_eq!(
+            tokens,
+            expected.map(|(kind, lexeme, line)| (kind.to_string(), lexeme.to_string(), line))
+        );
+
+        let (tokens, _) = Tokenizer::new("/// doc\r\n".to_string()).parse();
+        assert_eq!(tokens[0].literal, Some(Literal::String("doc".into())));
+
+        let (tokens, errors) = Tokenizer::new("a\n/* /* */\nb".to_string()).parse();
+        assert_eq!(tokens.len(), 2);
+        assert!(matches!(
+            &errors[..],
+            [Error::LexError(span, msg)]
+                if (span.line, span.column) == (2, 1) && msg == "Unterminated block comment."
+        ));
+    }
+}
@@ -1,10 +1,19 @@
+// 让 #[lox_native] 生成的 ::lox 路径在本 crate 内也能解析
+extern crate self as lox;
+
 pub mod ast_printer;
+pub mod class;
+pub mod control_flow;
+pub mod convert;
+pub mod diagnostic;
 pub mod environment;
 pub mod error;
 pub mod expr;
 pub mod function;
 pub mod interpreter;
 pub mod lex;
+pub mod natives;
 pub mod parser;
+pub mod repl;
 pub mod resolver;
 pub mod stmt;
@@ -5,20 +5,52 @@ use std::process::exit;
 
 use log::error;
 use lox::ast_printer::AstPrinter;
-use lox::environment::Value;
+use lox::diagnostic::{Diagnostic, Format, Renderer};
+use lox::error::Error;
+use lox::expr::ExprEnum;
 use lox::interpreter::Interpreter;
-use lox::lex::Literal;
-use lox::lex::Tokenizer;
+use lox::lex::{Token, TokenType, Tokenizer};
+use lox::natives;
 use lox::parser::Parser;
+use lox::repl::Repl;
 use lox::resolver::Resolver;
 
 fn main() {
     env_logger::builder()
         .format(|buf, record| writeln!(buf, "{}", record.args()))
         .init();
-    let args: Vec<String> = env::args().collect();
+    // 以 -- 开头的参数为选项，其余为命令和文件名
+    let (options, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    let mut format = Format::Rich;
+    for option in &options {
+        let parsed = match option.strip_prefix("--diagnostics=") {
+            Some(value) => value.parse(),
+            None => Err(format!("Unknown option: {}", option)),
+        };
+        match parsed {
+            Ok(f) => format = f,
+            Err(e) => {
+                error!("{}", e);
+                exit(64);
+            }
+        }
+    }
+
+    // 没有参数时进入交互模式
+    if args.len() == 1 || args[1] == "repl" {
+        let mut repl = Repl::new(new_interpreter, format);
+        if let Err(e) = repl.run() {
+            error!("{}", e);
+            exit(74);
+        }
+        return;
+    }
     if args.len() < 3 {
-        error!("Usage: {} tokenize <filename>", args[0]);
+        error!(
+            "Usage: {} [repl | <tokenize|parse|evaluate|run> <filename>] [--diagnostics=rich|plain|json]",
+            args[0]
+        );
         return;
     }
 
@@ -29,97 +61,105 @@ fn main() {
         error!("Failed to read file {}", filename);
         String::new()
     });
+    let renderer = Renderer::new(filename, &file_contents, format);
+    let report = |errors: &[Error]| {
+        errors
+            .iter()
+            .for_each(|e| eprintln!("{}", renderer.render(&Diagnostic::from(e))));
+    };
 
     match command.as_str() {
         "tokenize" => {
-            let mut tokenizer = Tokenizer::new(file_contents);
-            let (tokens, exit_code) = tokenizer.parse();
-            tokens.iter().for_each(|token| println!("{}", token));
-            exit(exit_code);
+            let mut tokenizer = Tokenizer::new(file_contents.clone());
+            let (tokens, errors) = tokenizer.parse();
+            report(&errors);
+            // 文档注释只供解析器和工具使用，输出与普通注释保持一致
+            tokens
+                .iter()
+                .filter(|token| token.token_type != TokenType::DocComment)
+                .for_each(|token| println!("{}", token));
+            if let Some(e) = errors.first() {
+                exit(e.exit_code());
+            }
         }
         "parse" => {
-            let mut tokenizer = Tokenizer::new(file_contents);
-            let (tokens, exit_code) = tokenizer.parse();
-            if exit_code != 0 {
-                exit(exit_code);
-            }
-            let mut parser = Parser::new(tokens);
-            let expression = parser.expression();
-            match expression {
-                Ok(expr) => {
-                    let mut ast_printer = AstPrinter::new();
-                    println!("{}", ast_printer.print(&expr));
-                }
-                Err(e) => {
-                    error!("{}", e);
-                    exit(65);
-                }
+            let mut tokenizer = Tokenizer::new(file_contents.clone());
+            let (tokens, errors) = tokenizer.parse();
+            if !errors.is_empty() {
+                report(&errors);
+                exit(errors[0].exit_code());
             }
+            let expr = parse_expression(tokens, &report);
+            let mut ast_printer = AstPrinter::new();
+            println!("{}", ast_printer.print(&expr));
         }
         "evaluate" => {
-            let mut tokenizer = Tokenizer::new(file_contents);
-            let (tokens, exit_code) = tokenizer.parse();
-            if exit_code != 0 {
-                exit(exit_code);
+            let mut tokenizer = Tokenizer::new(file_contents.clone());
+            let (tokens, errors) = tokenizer.parse();
+            if !errors.is_empty() {
+                report(&errors);
+                exit(errors[0].exit_code());
             }
-            let mut parser = Parser::new(tokens);
-            let expression = parser.expression();
-            match expression {
-                Ok(expr) => {
-                    let mut interpreter = Interpreter::new();
-                    let result = interpreter.evaluate(&expr);
-                    match result {
-                        Ok(literal) => println!("{}", literal.to_string()),
-                        Err(e) => {
-                            error!("{}", e);
-                            exit(70);
-                        }
-                    }
-                }
+            let expr = parse_expression(tokens, &report);
+            let mut interpreter = Interpreter::new();
+            let result = interpreter.evaluate(&expr);
+            match result {
+                Ok(literal) => println!("{}", literal),
                 Err(e) => {
-                    error!("{}", e);
-                    exit(65);
+                    exit_with(&report, e);
                 }
             }
         }
         "run" => {
-            let mut tokenizer = Tokenizer::new(file_contents);
-            let (tokens, exit_code) = tokenizer.parse();
-            if exit_code != 0 {
-                exit(exit_code);
-            }
+            let mut tokenizer = Tokenizer::new(file_contents.clone());
+            let (tokens, mut errors) = tokenizer.parse();
+            // 词法错误不会中断扫描，继续解析以便一次报告所有错误
             let mut parser = Parser::new(tokens);
-            let statements = parser.parse();
-            match statements {
-                Ok(s) => {
-                    let mut interpreter = Interpreter::new();
-                    interpreter.define_native_function("clock".to_string(), |_| {
-                        Ok(Value::Literal(Literal::Number(
-                            std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs_f64(),
-                        )))
-                    });
-                    let mut resolver = Resolver::new(&interpreter);
-                    resolver.resolve_statements(&s)?;
-                    match interpreter.interpret(&s) {
-                        Ok(_) => (),
-                        Err(e) => {
-                            error!("{}", e);
-                            exit(70);
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("{}", e);
-                    exit(65)
-                }
+            let (statements, parse_errors) = parser.parse();
+            errors.extend(parse_errors);
+            if !errors.is_empty() {
+                report(&errors);
+                exit(errors[0].exit_code());
+            }
+            let mut interpreter = new_interpreter();
+            let mut resolver = Resolver::new(&mut interpreter);
+            if let Err(e) = resolver.resolve_statements(&statements) {
+                exit_with(&report, e);
+            }
+            if let Err(e) = interpreter.interpret(&statements) {
+                exit_with(&report, e);
             }
         }
         _ => {
             error!("Unknown command: {}", command);
-            return;
         }
     }
 }
+
+/// 创建定义好内置函数的解释器
+fn new_interpreter() -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    natives::define(&mut interpreter);
+    interpreter
+}
+
+/// 解析单个表达式，连同解析器记录的非致命错误一起报告
+fn parse_expression(tokens: Vec<Token>, report: &impl Fn(&[Error])) -> ExprEnum {
+    let mut parser = Parser::new(tokens);
+    let expression = parser.expression();
+    let mut errors = parser.take_errors();
+    match expression {
+        Ok(expr) if errors.is_empty() => return expr,
+        Ok(_) => {}
+        Err(e) => errors.push(e),
+    }
+    report(&errors);
+    exit(errors[0].exit_code());
+}
+
+/// 报告错误并以该错误类别对应的退出码退出
+fn exit_with(report: &impl Fn(&[Error]), error: Error) -> ! {
+    let code = error.exit_code();
+    report(&[error]);
+    exit(code);
+}
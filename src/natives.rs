@@ -0,0 +1,165 @@
+use std::{cell::RefCell, rc::Rc};
+
+use indexmap::IndexMap;
+use lox_macro::lox_native;
+
+use crate::{
+    environment::Value,
+    error::{Error, RuntimeErrorKind},
+    function::Arity,
+    interpreter::Interpreter,
+    lex::Literal,
+};
+
+/// 在全局环境中定义所有内置函数
+pub fn define(interpreter: &mut Interpreter) {
+    interpreter.register_native(clock_native());
+    interpreter.define_native_function("len", Arity::Fixed(1), len);
+    interpreter.define_native_function("push", Arity::Variadic(2), push);
+    interpreter.define_native_function("pop", Arity::Fixed(1), pop);
+    interpreter.define_native_function("keys", Arity::Fixed(1), keys);
+    interpreter.define_native_function("values", Arity::Fixed(1), values);
+    interpreter.define_native_function("has", Arity::Fixed(2), has);
+    interpreter.define_native_function("remove", Arity::Fixed(2), remove);
+    interpreter.define_native_function("max", Arity::Variadic(1), max);
+    interpreter.define_native_function("min", Arity::Variadic(1), min);
+    interpreter.define_native_function("print_all", Arity::Variadic(0), print_all);
+}
+
+#[lox_native]
+fn clock() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs_f64()
+}
+
+/// 列表的元素个数、映射的键值对个数或字符串的字符个数
+fn len(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let len = match &arguments[0] {
+        Value::List(list) => list.borrow().len(),
+        Value::Map(map) => map.borrow().len(),
+        Value::Literal(Literal::String(s)) => s.chars().count(),
+        _ => {
+            return Err(Error::NativeError(
+                RuntimeErrorKind::Type,
+                "len() expects a list, a map or a string.".into(),
+            ))
+        }
+    };
+    Ok(Value::Literal(Literal::Number(len as f64)))
+}
+
+/// 依次把第二个及之后的参数追加到列表末尾
+fn push(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let mut arguments = arguments.into_iter();
+    let list = expect_list(arguments.next(), "push")?;
+    list.borrow_mut().extend(arguments);
+    Ok(Value::Literal(Literal::Nil))
+}
+
+fn pop(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let list = expect_list(arguments.into_iter().next(), "pop")?;
+    let value = list.borrow_mut().pop();
+    value.ok_or_else(|| {
+        Error::NativeError(
+            RuntimeErrorKind::Index,
+            "Can't pop from an empty list.".into(),
+        )
+    })
+}
+
+fn expect_list(value: Option<Value>, name: &str) -> Result<Rc<RefCell<Vec<Value>>>, Error> {
+    match value {
+        Some(Value::List(list)) => Ok(list),
+        _ => Err(Error::NativeError(
+            RuntimeErrorKind::Type,
+            format!("{}() expects a list as its first argument.", name),
+        )),
+    }
+}
+
+/// 按插入顺序返回所有键组成的列表
+fn keys(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let map = expect_map(arguments.into_iter().next(), "keys")?;
+    let keys = map.borrow().keys().cloned().map(Value::Literal).collect();
+    Ok(Value::List(Rc::new(RefCell::new(keys))))
+}
+
+fn values(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let map = expect_map(arguments.into_iter().next(), "values")?;
+    let values = map.borrow().values().cloned().collect();
+    Ok(Value::List(Rc::new(RefCell::new(values))))
+}
+
+fn has(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let mut arguments = arguments.into_iter();
+    let map = expect_map(arguments.next(), "has")?;
+    let key = expect_key(arguments.next())?;
+    let has = map.borrow().contains_key(&key);
+    Ok(Value::Literal(Literal::Boolean(has)))
+}
+
+/// 删除并返回键对应的值，键不存在时返回 nil
+fn remove(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let mut arguments = arguments.into_iter();
+    let map = expect_map(arguments.next(), "remove")?;
+    let key = expect_key(arguments.next())?;
+    // 保持其余键的插入顺序
+    let value = map.borrow_mut().shift_remove(&key);
+    Ok(value.unwrap_or(Value::Literal(Literal::Nil)))
+}
+
+fn max(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let numbers = expect_numbers(arguments, "max")?;
+    let max = numbers.into_iter().fold(f64::NEG_INFINITY, f64::max);
+    Ok(Value::Literal(Literal::Number(max)))
+}
+
+fn min(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let numbers = expect_numbers(arguments, "min")?;
+    let min = numbers.into_iter().fold(f64::INFINITY, f64::min);
+    Ok(Value::Literal(Literal::Number(min)))
+}
+
+/// 打印所有参数，以空格分隔
+fn print_all(_interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, Error> {
+    let parts: Vec<String> = arguments.iter().map(|value| value.to_string()).collect();
+    println!("{}", parts.join(" "));
+    Ok(Value::Literal(Literal::Nil))
+}
+
+fn expect_numbers(arguments: Vec<Value>, name: &str) -> Result<Vec<f64>, Error> {
+    arguments
+        .into_iter()
+        .map(|value| match value {
+            Value::Literal(Literal::Number(n)) => Ok(n),
+            _ => Err(Error::NativeError(
+                RuntimeErrorKind::Type,
+                format!("{}() expects numbers.", name),
+            )),
+        })
+        .collect()
+}
+
+fn expect_map(
+    value: Option<Value>,
+    name: &str,
+) -> Result<Rc<RefCell<IndexMap<Literal, Value>>>, Error> {
+    match value {
+        Some(Value::Map(map)) => Ok(map),
+        _ => Err(Error::NativeError(
+            RuntimeErrorKind::Type,
+            format!("{}() expects a map as its first argument.", name),
+        )),
+    }
+}
+
+fn expect_key(value: Option<Value>) -> Result<Literal, Error> {
+    value.and_then(|value| value.as_key()).ok_or_else(|| {
+        Error::NativeError(
+            RuntimeErrorKind::Type,
+            "Map keys must be strings, numbers or booleans.".into(),
+        )
+    })
+}
@@ -1,18 +1,64 @@
+use std::{collections::HashMap, rc::Rc};
+
 use crate::{
     error::Error,
-    expr::{Assignment, Binary, Call, ExprEnum, Grouping, Literal as ExprLiteral, Unary, Variable},
+    expr::{
+        Assignment, Binary, Call, ExprEnum, Get, Grouping, Index, IndexSet, Interpolation, Lambda,
+        List, Literal as ExprLiteral, Map, Set, Super, This, Unary, Variable,
+    },
     lex::{Literal, Token, TokenType},
-    stmt::{Block, Expression, FunctionDecl, If, Print, Return, StmtEnum, VarDecl, While},
+    stmt::{
+        Block, Break, Class, Continue, Expression, FunctionDecl, If, Parameter, Print, Return,
+        StmtEnum, VarDecl, While,
+    },
 };
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // 收集到的所有语法错误，解析不会在第一个错误处停止
+    errors: Vec<Error>,
+    // 文档注释不参与解析，按其后第一个 token 的下标保存，连续多行合并为一条
+    docs: HashMap<usize, String>,
+    // 正在解析的块的嵌套层数，块内出错时错误恢复不能越过块的右花括号
+    block_depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        let mut docs = HashMap::new();
+        let mut lines = Vec::new();
+        let mut code = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            if token.token_type == TokenType::DocComment {
+                if let Some(Literal::String(line)) = token.literal {
+                    lines.push(line);
+                }
+                continue;
+            }
+            if !lines.is_empty() {
+                docs.insert(code.len(), lines.join("\n"));
+                lines.clear();
+            }
+            code.push(token);
+        }
+        Self {
+            tokens: code,
+            current: 0,
+            errors: Vec::new(),
+            docs,
+            block_depth: 0,
+        }
+    }
+
+    /// 取出当前 token 之前的文档注释
+    fn take_doc(&mut self) -> Option<String> {
+        self.docs.remove(&self.current)
+    }
+
+    /// 记录一个不影响后续解析的错误，不需要进入错误恢复
+    fn error(&mut self, token: Token, message: impl Into<String>) {
+        self.errors.push(Error::ParseError(token, message.into()));
     }
 
     fn match_token(&mut self, token_type: TokenType) -> bool {
@@ -39,7 +85,13 @@ impl Parser {
         }
     }
 
-    fn is_at_end(&self) -> bool {
+    fn check_next(&self, token_type: TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
+    pub fn is_at_end(&self) -> bool {
         self.peek().token_type == TokenType::Eof
     }
 
@@ -59,12 +111,15 @@ impl Parser {
         if self.check_token(token_type) {
             Ok(self.advance())
         } else {
-            return Err(Error::ParseError(self.peek().clone(), message.into()));
+            Err(Error::ParseError(self.peek().clone(), message.into()))
         }
     }
 
-    #[allow(dead_code)]
+    /// 丢弃 token 直到下一个语句边界，避免一个错误引发一连串的错误
     fn synchronize(&mut self) {
+        if self.block_depth > 0 && self.check_token(TokenType::RightBrace) {
+            return;
+        }
         self.advance();
 
         while !self.is_at_end() {
@@ -80,7 +135,10 @@ impl Parser {
                 | TokenType::If
                 | TokenType::While
                 | TokenType::Print
-                | TokenType::Return => return,
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::Continue => return,
+                TokenType::RightBrace if self.block_depth > 0 => return,
                 _ => {}
             }
 
@@ -91,12 +149,16 @@ impl Parser {
 
 /**
  * program        → declaration* EOF ;
- * declaration    → var_decl | fun_decl | statement ;
+ * declaration    → class_decl | var_decl | fun_decl | statement ;
+ * class_decl     → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
  * var_decl       → "var" IDENTIFIER ( "=" expression )? ";" ;
  * fun_decl       → "fun" function ;
  * function       → IDENTIFIER "(" parameters? ")" block ;
- * parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
- * statement      → expr_stmt | for_stmt | if_stmt | print_stmt | return_stmt | while_stmt | block ;
+ * lambda         → "fun" "(" parameters? ")" ( block | "=>" expression ) ;
+ * parameters     → parameter ( "," parameter )* ( "," "..." IDENTIFIER )? | "..." IDENTIFIER ;
+ * parameter      → IDENTIFIER ( "=" expression )? ;
+ * statement      → expr_stmt | for_stmt | if_stmt | print_stmt | return_stmt | while_stmt
+ *                  | break_stmt | continue_stmt | block ;
  * for_stmt       → "for" "(" ( var_decl | expr_stmt | ";" ) expression? ";" expression? ")" statement ;
  * if_stmt        → "if" "(" expression ")" statement ( "else" statement )? ;
  * while_stmt     → "while" "(" expression ")" statement ;
@@ -104,8 +166,11 @@ impl Parser {
  * expr_stmt      → expression ";";
  * print_stmt     → "print" expression ";";
  * return_stmt    → "return" expression? ";";
+ * break_stmt     → "break" ";";
+ * continue_stmt  → "continue" ";";
  * expression     → assignment;
- * assignment     → IDENTIFIER "=" assignment | logic_or;
+ * assignment     → ( call "." IDENTIFIER | call "[" expression "]" | IDENTIFIER ) "=" assignment
+ *                  | logic_or;
  * logic_or       → logic_and ( "or" logic_and )* ;
  * logic_and      → equality ( "and" equality )* ;
  * equality       → comparison ( ( "!=" | "==" ) comparison )* ;
@@ -113,25 +178,42 @@ impl Parser {
  * term           → factor ( ( "-" | "+" ) factor )* ;
  * factor         → unary ( ( "/" | "*" ) unary )* ;
  * unary          → ( "!" | "-" ) unary | call ;
- * call           → primary ( "(" arguments? ")" )* ;
+ * call           → primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )* ;
  * arguments      → expression ( "," expression )* ;
- * primary        → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" | IDENTIFIER ;
+ * primary        → NUMBER | STRING | interpolation | "true" | "false" | "nil" | "this" | "(" expression ")" | IDENTIFIER
+ *                  | "super" "." IDENTIFIER | "[" ( expression ( "," expression )* ","? )? "]"
+ *                  | "{" ( entry ( "," entry )* ","? )? "}" | lambda ;
+ * entry          → expression ":" expression ;
+ * interpolation  → ( INTERPOLATION expression )+ STRING ;
  */
 impl Parser {
-    pub fn parse(&mut self) -> Result<Vec<StmtEnum>, Error> {
+    /// 解析整个程序，返回成功解析的语句以及所有语法错误
+    pub fn parse(&mut self) -> (Vec<StmtEnum>, Vec<Error>) {
         let mut statements = Vec::new();
 
         while !self.is_at_end() {
-            statements.push(self.declaration()?);
-            // match self.declaration() {
-            //     Ok(stmt) => statements.push(stmt),
-            //     Err(_) => {
-            //         self.synchronize();
-            //     }
-            // }
+            if let Some(stmt) = self.declaration_with_recovery() {
+                statements.push(stmt);
+            }
         }
 
-        Ok(statements)
+        (statements, std::mem::take(&mut self.errors))
+    }
+
+    /// 取出解析过程中记录的非致命错误，单独调用 `expression` 时需要检查
+    pub fn take_errors(&mut self) -> Vec<Error> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn declaration_with_recovery(&mut self) -> Option<StmtEnum> {
+        match self.declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize();
+                None
+            }
+        }
     }
 
     fn return_stmt(&mut self) -> Result<StmtEnum, Error> {
@@ -145,7 +227,32 @@ impl Parser {
         Ok(StmtEnum::Return(Return::new(keyword, value.map(Box::new))))
     }
 
-    fn function(&mut self, kind: String) -> Result<StmtEnum, Error> {
+    fn class_decl(&mut self, doc: Option<String>) -> Result<StmtEnum, Error> {
+        let name = self
+            .consume(TokenType::Identifier, "Expected class name.")?
+            .clone();
+
+        let superclass = if self.match_token(TokenType::Less) {
+            let name = self
+                .consume(TokenType::Identifier, "Expected superclass name.")?
+                .clone();
+            Some(Variable::new(name))
+        } else {
+            None
+        };
+        self.consume(TokenType::LeftBrace, "Expected '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check_token(TokenType::RightBrace) && !self.is_at_end() {
+            let doc = self.take_doc();
+            methods.push(self.function("method".to_string(), doc)?);
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after class body.")?;
+
+        Ok(StmtEnum::Class(Class::new(name, superclass, methods, doc)))
+    }
+
+    fn function(&mut self, kind: String, doc: Option<String>) -> Result<FunctionDecl, Error> {
         let name = self
             .consume(TokenType::Identifier, format!("Expected {} name.", kind))?
             .clone();
@@ -153,41 +260,103 @@ impl Parser {
             TokenType::LeftParen,
             format!("Expected '(' after {} name.", kind),
         )?;
+        let (parameters, rest) = self.parameters()?;
+        self.consume(
+            TokenType::LeftBrace,
+            format!("Expected '{{' before {} body.", kind),
+        )?;
+        let body = self.block()?;
+        Ok(FunctionDecl::new(name, parameters, rest, body, doc))
+    }
+
+    /// 调用前 fun 关键字已经被消费；箭头形式的函数体等价于只有一条 return 语句的块
+    fn lambda(&mut self) -> Result<ExprEnum, Error> {
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expected '(' after 'fun'.")?;
+        let (parameters, rest) = self.parameters()?;
+        let body = if self.match_token(TokenType::Arrow) {
+            let arrow = self.previous().clone();
+            let value = self.expression()?;
+            let span = arrow.span.merge(&value.span());
+            Block::new(
+                vec![StmtEnum::Return(Return::new(arrow, Some(Box::new(value))))],
+                span,
+            )
+        } else {
+            self.consume(TokenType::LeftBrace, "Expected '{' before function body.")?;
+            self.block()?
+        };
+        let name = Token::new(TokenType::Fun, "anonymous".into(), None, keyword.span);
+        Ok(ExprEnum::Lambda(Lambda::new(Rc::new(FunctionDecl::new(
+            name, parameters, rest, body, None,
+        )))))
+    }
 
+    /// 调用前左括号已经被消费，解析到右括号为止，返回普通参数和剩余参数
+    fn parameters(&mut self) -> Result<(Vec<Parameter>, Option<Token>), Error> {
         let mut parameters = Vec::new();
+        let mut rest = None;
         if !self.check_token(TokenType::RightParen) {
             loop {
                 if parameters.len() >= 255 {
-                    return Err(Error::ParseError(
-                        self.peek().clone(),
-                        "Can't have more than 255 parameters.".into(),
-                    ));
+                    self.error(self.peek().clone(), "Can't have more than 255 parameters.");
                 }
-                parameters.push(
-                    self.consume(TokenType::Identifier, "Expected parameter name.")
-                        .map(|token| token.clone())?,
-                );
+                if self.match_token(TokenType::Ellipsis) {
+                    rest = Some(
+                        self.consume(TokenType::Identifier, "Expected rest parameter name.")
+                            .cloned()?,
+                    );
+                    break;
+                }
+                let name = self
+                    .consume(TokenType::Identifier, "Expected parameter name.")
+                    .cloned()?;
+                let default = if self.match_token(TokenType::Equal) {
+                    Some(self.expression()?)
+                } else {
+                    // 可选参数之后不能再出现必选参数
+                    if parameters.iter().any(|p: &Parameter| p.default.is_some()) {
+                        self.error(
+                            name.clone(),
+                            "Parameter without a default value can't follow one with a default.",
+                        );
+                    }
+                    None
+                };
+                parameters.push(Parameter::new(name, default));
                 if !self.match_token(TokenType::Comma) {
                     break;
                 }
             }
         }
-        self.consume(TokenType::RightParen, "Expected ')' after parameters.")?;
-        self.consume(
-            TokenType::LeftBrace,
-            format!("Expected '{{' before {} body.", kind),
-        )?;
-        Ok(StmtEnum::FunctionDecl(FunctionDecl::new(
-            name,
-            parameters,
-            self.block()?,
-        )))
+        let message = if rest.is_some() {
+            "Rest parameter must be the last parameter."
+        } else {
+            "Expected ')' after parameters."
+        };
+        self.consume(TokenType::RightParen, message)?;
+        Ok((parameters, rest))
     }
 
     fn call(&mut self) -> Result<ExprEnum, Error> {
         let mut expr = self.primary()?;
-        while self.match_token(TokenType::LeftParen) {
-            expr = self.finish_call(expr)?;
+        loop {
+            if self.match_token(TokenType::LeftParen) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(TokenType::Dot) {
+                let name = self
+                    .consume(TokenType::Identifier, "Expected property name after '.'.")?
+                    .clone();
+                expr = ExprEnum::Get(Get::new(Box::new(expr), name));
+            } else if self.match_token(TokenType::LeftBracket) {
+                let index = self.expression()?;
+                let bracket = self
+                    .consume(TokenType::RightBracket, "Expected ']' after index.")?
+                    .clone();
+                expr = ExprEnum::Index(Index::new(Box::new(expr), bracket, Box::new(index)));
+            } else {
+                break;
+            }
         }
         Ok(expr)
     }
@@ -198,10 +367,7 @@ impl Parser {
             arguments.push(self.expression()?);
             while self.match_token(TokenType::Comma) {
                 if arguments.len() >= 255 {
-                    return Err(Error::ParseError(
-                        self.peek().clone(),
-                        "Can't have more than 255 arguments.".into(),
-                    ));
+                    self.error(self.peek().clone(), "Can't have more than 255 arguments.");
                 }
                 arguments.push(self.expression()?);
             }
@@ -216,21 +382,25 @@ impl Parser {
     }
 
     fn while_stmt(&mut self) -> Result<StmtEnum, Error> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expected '(' after 'while'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expected ')' after condition.")?;
         let body = self.statement()?;
         Ok(StmtEnum::While(While::new(
+            keyword,
             Box::new(condition),
             Box::new(body),
+            None,
         )))
     }
 
     fn for_stmt(&mut self) -> Result<StmtEnum, Error> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expected '(' after 'for'.")?;
 
         let initializer = if self.match_token(TokenType::Var) {
-            Some(self.var_decl()?)
+            Some(self.var_decl(None)?)
         } else if self.match_token(TokenType::Semicolon) {
             None
         } else {
@@ -254,38 +424,45 @@ impl Parser {
         };
 
         let mut body = self.statement()?;
-        if let Some(increment) = increment {
-            body = StmtEnum::Block(Block::new(vec![
-                body,
-                StmtEnum::Expression(Expression::new(Box::new(increment))),
-            ]));
-        }
-
+        // 脱糖生成的节点都使用整个 for 语句的区间
+        let span = keyword.span.merge(&body.span());
+        // 递增子句交给 While 执行，这样 continue 跳过循环体剩余部分后仍会执行它
         body = StmtEnum::While(While::new(
-            Box::new(
-                condition.unwrap_or(ExprEnum::Literal(ExprLiteral::new(Literal::Boolean(true)))),
-            ),
+            keyword.clone(),
+            Box::new(condition.unwrap_or(ExprEnum::Literal(ExprLiteral::new(
+                Literal::Boolean(true),
+                keyword.span,
+            )))),
             Box::new(body),
+            increment.map(Box::new),
         ));
 
         if let Some(initializer) = initializer {
-            body = StmtEnum::Block(Block::new(vec![initializer, body]));
+            body = StmtEnum::Block(Block::new(vec![initializer, body], span));
         }
 
         Ok(body)
     }
 
     fn declaration(&mut self) -> Result<StmtEnum, Error> {
-        if self.match_token(TokenType::Var) {
-            self.var_decl()
-        } else if self.match_token(TokenType::Fun) {
-            self.function("function".to_string())
+        // 不在声明之前的文档注释和普通注释一样被忽略
+        let doc = self.take_doc();
+        if self.match_token(TokenType::Class) {
+            self.class_decl(doc)
+        } else if self.match_token(TokenType::Var) {
+            self.var_decl(doc)
+        } else if self.check_token(TokenType::Fun) && self.check_next(TokenType::Identifier) {
+            // fun 后面不是名字时是匿名函数表达式
+            self.advance();
+            Ok(StmtEnum::FunctionDecl(
+                self.function("function".to_string(), doc)?,
+            ))
         } else {
             self.statement()
         }
     }
 
-    fn var_decl(&mut self) -> Result<StmtEnum, Error> {
+    fn var_decl(&mut self, doc: Option<String>) -> Result<StmtEnum, Error> {
         let name = self
             .consume(TokenType::Identifier, "Expected variable name.")?
             .clone();
@@ -301,7 +478,7 @@ impl Parser {
             "Expected ';' after variable declaration.",
         )?;
 
-        Ok(StmtEnum::VarDecl(VarDecl::new(name, initializer)))
+        Ok(StmtEnum::VarDecl(VarDecl::new(name, initializer, doc)))
     }
 
     fn statement(&mut self) -> Result<StmtEnum, Error> {
@@ -317,12 +494,21 @@ impl Parser {
             self.for_stmt()
         } else if self.match_token(TokenType::Return) {
             self.return_stmt()
+        } else if self.match_token(TokenType::Break) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::Semicolon, "Expected ';' after 'break'.")?;
+            Ok(StmtEnum::Break(Break::new(keyword)))
+        } else if self.match_token(TokenType::Continue) {
+            let keyword = self.previous().clone();
+            self.consume(TokenType::Semicolon, "Expected ';' after 'continue'.")?;
+            Ok(StmtEnum::Continue(Continue::new(keyword)))
         } else {
             self.expr_stmt()
         }
     }
 
     fn if_stmt(&mut self) -> Result<StmtEnum, Error> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expected '(' after 'if'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expected ')' after condition.")?;
@@ -333,25 +519,36 @@ impl Parser {
             None
         };
         Ok(StmtEnum::If(If::new(
+            keyword,
             Box::new(condition),
             Box::new(then_branch),
             else_branch,
         )))
     }
 
+    /// 调用前左花括号已经被消费
     fn block(&mut self) -> Result<Block, Error> {
+        let left_brace = self.previous().span;
         let mut statements = Vec::new();
+        // 每条声明单独恢复，块中的一个错误不会连带丢弃整个块
+        self.block_depth += 1;
         while !self.check_token(TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration()?);
+            if let Some(stmt) = self.declaration_with_recovery() {
+                statements.push(stmt);
+            }
         }
-        self.consume(TokenType::RightBrace, "Expect '}' after block")?;
-        Ok(Block::new(statements))
+        self.block_depth -= 1;
+        let right_brace = self
+            .consume(TokenType::RightBrace, "Expect '}' after block")?
+            .span;
+        Ok(Block::new(statements, left_brace.merge(&right_brace)))
     }
 
     fn print_stmt(&mut self) -> Result<StmtEnum, Error> {
+        let keyword = self.previous().clone();
         let expr = self.expression()?;
         self.consume(TokenType::Semicolon, "Expected ';' after value.")?;
-        Ok(StmtEnum::Print(Print::new(Box::new(expr))))
+        Ok(StmtEnum::Print(Print::new(keyword, Box::new(expr))))
     }
 
     fn expr_stmt(&mut self) -> Result<StmtEnum, Error> {
@@ -376,10 +573,22 @@ impl Parser {
                     variable.name,
                     Box::new(value),
                 ))),
-                _ => Err(Error::ParseError(
-                    equals.clone(),
-                    "Invalid assignment target.".into(),
-                )),
+                ExprEnum::Get(get) => Ok(ExprEnum::Set(Set::new(
+                    get.object,
+                    get.name,
+                    Box::new(value),
+                ))),
+                ExprEnum::Index(index) => Ok(ExprEnum::IndexSet(IndexSet::new(
+                    index.object,
+                    index.bracket,
+                    index.index,
+                    Box::new(value),
+                ))),
+                // 赋值目标错误不影响解析状态，记录后继续解析
+                _ => {
+                    self.error(equals, "Invalid assignment target.");
+                    Ok(expr)
+                }
             };
         }
 
@@ -489,28 +698,292 @@ impl Parser {
     }
 
     fn primary(&mut self) -> Result<ExprEnum, Error> {
-        let token = self.advance();
-
-        match token.token_type {
-            TokenType::False => Ok(ExprEnum::Literal(ExprLiteral::new(Literal::Boolean(false)))),
-            TokenType::True => Ok(ExprEnum::Literal(ExprLiteral::new(Literal::Boolean(true)))),
-            TokenType::Nil => Ok(ExprEnum::Literal(ExprLiteral::new(Literal::Nil))),
-            TokenType::Number => Ok(ExprEnum::Literal(ExprLiteral::new(
-                token.literal.clone().unwrap(),
-            ))),
-            TokenType::String => Ok(ExprEnum::Literal(ExprLiteral::new(
-                token.literal.clone().unwrap(),
-            ))),
+        // 先 peek 而不是直接消费，出错的 token 留给 synchronize 处理
+        let token = self.peek().clone();
+
+        let expr = match token.token_type {
+            TokenType::False => {
+                ExprEnum::Literal(ExprLiteral::new(Literal::Boolean(false), token.span))
+            }
+            TokenType::True => {
+                ExprEnum::Literal(ExprLiteral::new(Literal::Boolean(true), token.span))
+            }
+            TokenType::Nil => ExprEnum::Literal(ExprLiteral::new(Literal::Nil, token.span)),
+            TokenType::This => ExprEnum::This(This::new(token)),
+            TokenType::Number | TokenType::String => {
+                ExprEnum::Literal(ExprLiteral::new(token.literal.unwrap(), token.span))
+            }
+            TokenType::Identifier => ExprEnum::Variable(Variable::new(token)),
+            TokenType::Super => {
+                self.advance();
+                self.consume(TokenType::Dot, "Expected '.' after 'super'.")?;
+                let method = self
+                    .consume(TokenType::Identifier, "Expected superclass method name.")?
+                    .clone();
+                return Ok(ExprEnum::Super(Super::new(token, method)));
+            }
             TokenType::LeftParen => {
-                let expr = self.expression();
-                self.consume(TokenType::RightParen, "Expected ')' after expression")?;
-                Ok(ExprEnum::Grouping(Grouping::new(Box::new(expr?))))
+                self.advance();
+                let expr = self.expression()?;
+                let right_paren = self
+                    .consume(TokenType::RightParen, "Expected ')' after expression")?
+                    .span;
+                return Ok(ExprEnum::Grouping(Grouping::new(
+                    Box::new(expr),
+                    token.span.merge(&right_paren),
+                )));
             }
-            TokenType::Identifier => Ok(ExprEnum::Variable(Variable::new(token.clone()))),
-            _ => Err(Error::ParseError(
-                token.clone(),
-                format!("Expected expression, got {}", token.lexeme),
-            )),
+            TokenType::Interpolation => {
+                self.advance();
+                return self.interpolation(token);
+            }
+            TokenType::LeftBracket => {
+                self.advance();
+                return self.list(token);
+            }
+            TokenType::Fun => {
+                self.advance();
+                return self.lambda();
+            }
+            // 语句开头的左花括号已经被解析为块，这里只会出现在表达式中
+            TokenType::LeftBrace => {
+                self.advance();
+                return self.map(token);
+            }
+            _ => {
+                return Err(Error::ParseError(
+                    token.clone(),
+                    format!("Expected expression, got {}", token.lexeme),
+                ))
+            }
+        };
+        self.advance();
+        Ok(expr)
+    }
+
+    /// 调用前第一个 Interpolation token 已经被消费，每个插值表达式之后是下一个片段，
+    /// 最后一个片段是 String token
+    fn interpolation(&mut self, start: Token) -> Result<ExprEnum, Error> {
+        let mut parts = Vec::new();
+        Self::push_segment(&mut parts, &start);
+        parts.push(self.interpolated_expression(&start)?);
+        while self.match_token(TokenType::Interpolation) {
+            let segment = self.previous().clone();
+            Self::push_segment(&mut parts, &segment);
+            parts.push(self.interpolated_expression(&segment)?);
+        }
+        let end = self
+            .consume(
+                TokenType::String,
+                "Expected '}' after interpolated expression.",
+            )?
+            .clone();
+        Self::push_segment(&mut parts, &end);
+        Ok(ExprEnum::Interpolation(Interpolation::new(
+            parts,
+            start.span.merge(&end.span),
+        )))
+    }
+
+    /// 解析 `${` 之后的表达式，紧跟着以 `}` 开头的字符串片段说明插值为空，
+    /// 以引号开头的则是嵌套的字符串字面量
+    fn interpolated_expression(&mut self, segment: &Token) -> Result<ExprEnum, Error> {
+        let next = self.peek();
+        if matches!(
+            next.token_type,
+            TokenType::String | TokenType::Interpolation
+        ) && next.lexeme.starts_with('}')
+        {
+            return Err(Error::ParseError(
+                segment.clone(),
+                "Expected expression inside '${}'.".to_string(),
+            ));
+        }
+        self.expression()
+    }
+
+    /// 省略空的字符串片段
+    fn push_segment(parts: &mut Vec<ExprEnum>, segment: &Token) {
+        if let Some(Literal::String(s)) = &segment.literal {
+            if !s.is_empty() {
+                parts.push(ExprEnum::Literal(ExprLiteral::new(
+                    Literal::String(s.clone()),
+                    segment.span,
+                )));
+            }
+        }
+    }
+
+    /// 调用前左方括号已经被消费，允许最后一个元素后面跟一个逗号
+    fn list(&mut self, left_bracket: Token) -> Result<ExprEnum, Error> {
+        let mut elements = Vec::new();
+        while !self.check_token(TokenType::RightBracket) {
+            elements.push(self.expression()?);
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        let right_bracket = self
+            .consume(TokenType::RightBracket, "Expected ']' after list elements.")?
+            .span;
+        Ok(ExprEnum::List(List::new(
+            elements,
+            left_bracket.span.merge(&right_bracket),
+        )))
+    }
+
+    /// 调用前左花括号已经被消费，允许最后一个键值对后面跟一个逗号
+    fn map(&mut self, left_brace: Token) -> Result<ExprEnum, Error> {
+        let mut entries = Vec::new();
+        while !self.check_token(TokenType::RightBrace) {
+            let key = self.expression()?;
+            let colon = self
+                .consume(TokenType::Colon, "Expected ':' after map key.")?
+                .clone();
+            let value = self.expression()?;
+            entries.push((key, colon, value));
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        let right_brace = self
+            .consume(TokenType::RightBrace, "Expected '}' after map entries.")?
+            .span;
+        Ok(ExprEnum::Map(Map::new(
+            entries,
+            left_brace.span.merge(&right_brace),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::Tokenizer;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_reports_every_error() {
+        let source = r#"
+        var a = ;
+        print a
+        var b = 1;
+        1 = 2;
+        print b;
+        fun f() {
+            var c = ;
+            print c
+        }
+        print f;
+        "#;
+        let mut tokenizer = Tokenizer::new(source.to_string());
+        let (tokens, lex_errors) = tokenizer.parse();
+        assert!(lex_errors.is_empty());
+        let mut parser = Parser::new(tokens);
+        let (statements, errors) = parser.parse();
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|e| match e {
+                Error::ParseError(token, _) => token.line_number(),
+                _ => panic!("unexpected error {:?}", e),
+            })
+            .collect();
+        // 函数体中的错误各自报告，右花括号处没有多余的错误
+        assert_eq!(lines, vec![2, 4, 5, 8, 10]);
+        // 出错的语句被丢弃，其余的语句仍然保留
+        assert_eq!(statements.len(), 4);
+    }
+
+    #[test]
+    fn test_expression_reports_recorded_errors() {
+        let (tokens, _) = Tokenizer::new("1 = 2".to_string()).parse();
+        let mut parser = Parser::new(tokens);
+        assert!(parser.expression().is_ok());
+        let errors = parser.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().contains("Invalid assignment target."));
+        assert!(parser.take_errors().is_empty());
+    }
+
+    #[test]
+    fn test_node_spans() {
+        let source = "if (a) {\n  print (1 + 2) * b;\n}";
+        let (tokens, _) = Tokenizer::new(source.to_string()).parse();
+        let (statements, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty());
+
+        let span = statements[0].span();
+        assert_eq!(&source[span.start..span.end], source);
+        let StmtEnum::If(stmt) = &statements[0] else {
+            panic!("expected if statement");
+        };
+        let StmtEnum::Block(block) = stmt.then_branch.as_ref() else {
+            panic!("expected block");
+        };
+        let StmtEnum::Print(print) = &block.statements[0] else {
+            panic!("expected print statement");
+        };
+        let span = print.expression.span();
+        assert_eq!(&source[span.start..span.end], "(1 + 2) * b");
+        assert_eq!((span.line, span.column), (2, 9));
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let source = r#"
+        /// Adds two numbers.
+        ///
+        /// Returns their sum.
+        fun add(a, b) { return a + b; }
+
+        /// The answer.
+        var answer = /* inline */ 42;
+
+        /// Ignored, not before a declaration.
+        print answer;
+
+        /// A point.
+        class Point {
+            /// Creates a point.
+            init(x, y) { this.x = x; }
+            plain() {}
+        }
+        var undocumented;
+        "#;
+        let (tokens, errors) = Tokenizer::new(source.to_string()).parse();
+        assert!(errors.is_empty());
+        let (statements, errors) = Parser::new(tokens).parse();
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let docs: Vec<_> = statements.iter().map(|stmt| stmt.doc()).collect();
+        assert_eq!(
+            docs,
+            [
+                Some("Adds two numbers.\n\nReturns their sum."),
+                Some("The answer."),
+                None,
+                Some("A point."),
+                None
+            ]
+        );
+        let StmtEnum::Class(class) = &statements[3] else {
+            panic!("expected class declaration");
+        };
+        let methods: Vec<_> = class.methods.iter().map(|m| m.doc.as_deref()).collect();
+        assert_eq!(methods, [Some("Creates a point."), None]);
+    }
+
+    #[test]
+    fn test_empty_interpolation() {
+        for source in [r#""${}";"#, r#""a${}b${x}c";"#, r#""a${"x"}b${}c";"#] {
+            let (tokens, _) = Tokenizer::new(source.to_string()).parse();
+            let (_, errors) = Parser::new(tokens).parse();
+            assert_eq!(errors.len(), 1, "{}", source);
+            let Error::ParseError(token, message) = &errors[0] else {
+                panic!("expected a parse error");
+            };
+            assert_eq!(message, "Expected expression inside '${}'.");
+            assert!(token.lexeme.ends_with("${"), "{}", token.lexeme);
         }
     }
 }
@@ -0,0 +1,318 @@
+use std::{cell::RefCell, env, fs, path::PathBuf, rc::Rc};
+
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::FileHistory, validate::Validator, Context, Editor, Helper,
+};
+
+use crate::{
+    diagnostic::{Diagnostic, Format, Renderer},
+    environment::{Environment, Value},
+    error::Error,
+    interpreter::Interpreter,
+    lex::{TokenType, Tokenizer, KEYWORDS},
+    parser::Parser,
+    resolver::Resolver,
+    stmt::{Expression, StmtEnum},
+};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+const HISTORY_FILE: &str = ".lox_history";
+const HELP: &str = "\
+:env          list global variables
+:load <file>  run a file in the current session
+:reset        discard all definitions
+:help         show this message";
+
+/// 交互式解释器，所有输入共享同一个 Interpreter，因此全局变量在多次输入之间保持
+pub struct Repl {
+    interpreter: Interpreter,
+    // :reset 时用来重新创建解释器
+    make_interpreter: fn() -> Interpreter,
+    format: Format,
+    // 已经执行过的全部输入，新输入的 token 区间接在它后面，
+    // 既保证不同输入中的 token 互不相同，也让诊断信息可以引用之前的行
+    history: String,
+}
+
+impl Repl {
+    pub fn new(make_interpreter: fn() -> Interpreter, format: Format) -> Self {
+        Self {
+            interpreter: make_interpreter(),
+            make_interpreter,
+            format,
+            history: String::new(),
+        }
+    }
+
+    /// 逐行读取输入直到 EOF，花括号未闭合时继续读取下一行
+    pub fn run(&mut self) -> rustyline::Result<()> {
+        let mut editor = Editor::<LoxHelper, FileHistory>::new()?;
+        editor.set_helper(Some(LoxHelper {
+            globals: self.interpreter.globals(),
+        }));
+        let history_path = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        if let Some(path) = &history_path {
+            // 第一次运行时历史文件还不存在
+            let _ = editor.load_history(path);
+        }
+
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                // Ctrl-C 丢弃当前未完成的输入
+                Err(ReadlineError::Interrupted) => {
+                    buffer.clear();
+                    continue;
+                }
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e),
+            };
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                editor.add_history_entry(line.trim())?;
+                self.command(line.trim());
+                // :reset 之后补全需要看到新的全局环境
+                if let Some(helper) = editor.helper_mut() {
+                    helper.globals = self.interpreter.globals();
+                }
+                continue;
+            }
+
+            buffer.push_str(&line);
+            buffer.push('\n');
+            if !is_complete(&buffer) {
+                continue;
+            }
+            let source = std::mem::take(&mut buffer);
+            if source.trim().is_empty() {
+                continue;
+            }
+            editor.add_history_entry(source.trim_end())?;
+            self.execute(&source);
+        }
+
+        if let Some(path) = &history_path {
+            editor.save_history(path)?;
+        }
+        Ok(())
+    }
+
+    /// 处理以冒号开头的元命令
+    pub fn command(&mut self, line: &str) {
+        let (name, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, argument)| (name, argument.trim()));
+        match name {
+            ":env" => {
+                for (name, value) in self.interpreter.globals().borrow().bindings() {
+                    println!("{} = {}", name, value);
+                }
+            }
+            ":load" if !argument.is_empty() => match fs::read_to_string(argument) {
+                Ok(source) => self.execute(&source),
+                Err(e) => eprintln!("Failed to read file {}: {}", argument, e),
+            },
+            ":reset" => {
+                self.interpreter = (self.make_interpreter)();
+                self.history.clear();
+            }
+            ":help" => println!("{}", HELP),
+            _ => eprintln!("Unknown command: {}, type :help for help", line),
+        }
+    }
+
+    fn execute(&mut self, source: &str) {
+        match self.eval(source) {
+            Ok(Some(value)) => println!("{}", value),
+            Ok(None) => {}
+            Err(errors) => self.report(&errors),
+        }
+    }
+
+    /// 执行一段输入；如果输入是一个不带分号的表达式，返回它的值
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, Vec<Error>> {
+        let line = self.history.lines().count() + 1;
+        let offset = self.history.len();
+        self.history.push_str(source);
+        if !source.ends_with('\n') {
+            self.history.push('\n');
+        }
+
+        let (tokens, errors) = Tokenizer::new(source.to_string())
+            .starting_at(offset, line)
+            .parse();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut parser = Parser::new(tokens.clone());
+        if let Ok(expr) = parser.expression() {
+            if parser.is_at_end() {
+                let errors = parser.take_errors();
+                if !errors.is_empty() {
+                    return Err(errors);
+                }
+                let statements = [StmtEnum::Expression(Expression::new(Box::new(expr)))];
+                self.resolve(&statements)?;
+                let StmtEnum::Expression(stmt) = &statements[0] else {
+                    unreachable!()
+                };
+                return self
+                    .interpreter
+                    .evaluate(&stmt.expression)
+                    .map(Some)
+                    .map_err(|e| vec![e]);
+            }
+        }
+
+        let (statements, errors) = Parser::new(tokens).parse();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        self.resolve(&statements)?;
+        self.interpreter
+            .interpret(&statements)
+            .map(|_| None)
+            .map_err(|e| vec![e])
+    }
+
+    fn resolve(&mut self, statements: &[StmtEnum]) -> Result<(), Vec<Error>> {
+        Resolver::new(&mut self.interpreter)
+            .resolve_statements(statements)
+            .map_err(|e| vec![e])
+    }
+
+    fn report(&self, errors: &[Error]) {
+        let renderer = Renderer::new("<repl>", &self.history, self.format);
+        errors
+            .iter()
+            .for_each(|e| eprintln!("{}", renderer.render(&Diagnostic::from(e))));
+    }
+}
+
+/// 补全关键字以及当前定义的全局变量
+struct LoxHelper {
+    globals: Rc<RefCell<Environment>>,
+}
+
+impl Completer for LoxHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        // 光标前连续的标识符字符就是要补全的前缀
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let candidates = completions(&line[start..pos], &self.globals.borrow());
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for LoxHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LoxHelper {}
+
+impl Validator for LoxHelper {}
+
+impl Helper for LoxHelper {}
+
+fn completions(prefix: &str, globals: &Environment) -> Vec<String> {
+    let mut candidates: Vec<String> = KEYWORDS
+        .iter()
+        .map(|keyword| keyword.to_string())
+        .chain(globals.bindings().into_iter().map(|(name, _)| name))
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// 左花括号多于右花括号时认为输入还没有结束
+fn is_complete(source: &str) -> bool {
+    let (tokens, _) = Tokenizer::new(source.to_string()).parse();
+    let depth = tokens
+        .iter()
+        .fold(0i32, |depth, token| match token.token_type {
+            TokenType::LeftBrace => depth + 1,
+            TokenType::RightBrace => depth - 1,
+            _ => depth,
+        });
+    depth <= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_persists_between_inputs() {
+        let mut repl = Repl::new(Interpreter::new, Format::Plain);
+        assert!(matches!(repl.eval("var a = 1;\n"), Ok(None)));
+        assert!(matches!(
+            repl.eval("fun add(b) {\n  return a + b;\n}\n"),
+            Ok(None)
+        ));
+        let value = repl.eval("add(2)\n").unwrap().unwrap();
+        assert_eq!(value.to_string(), "3");
+        // 出错后状态仍然可用
+        assert!(repl.eval("print missing;\n").is_err());
+        assert!(repl.eval("var = ;\n").is_err());
+        // 表达式快速路径同样要报告非致命的语法错误
+        assert!(repl.eval("1 = 2\n").is_err());
+        assert!(repl.eval("fun (a = 1, b) => a\n").is_err());
+        let value = repl.eval("a = a + 10\n").unwrap().unwrap();
+        assert_eq!(value.to_string(), "11");
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("print 1;\n"));
+        assert!(!is_complete("fun f() {\n"));
+        assert!(!is_complete("{ {\n}\n"));
+        assert!(is_complete("{ {\n}\n}\n"));
+        assert!(is_complete("print \"{\";\n"));
+    }
+
+    #[test]
+    fn test_load_and_reset() {
+        let path = env::temp_dir().join(format!("lox_repl_load_{}.lox", std::process::id()));
+        fs::write(&path, "var loaded = 42;\nfun twice(x) { return x * 2; }").unwrap();
+        let mut repl = Repl::new(Interpreter::new, Format::Plain);
+        repl.command(&format!(":load {}", path.display()));
+        fs::remove_file(&path).unwrap();
+        let value = repl.eval("twice(loaded)\n").unwrap().unwrap();
+        assert_eq!(value.to_string(), "84");
+
+        repl.command(":reset");
+        assert!(repl.eval("loaded\n").is_err());
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut repl = Repl::new(Interpreter::new, Format::Plain);
+        repl.eval("var counter = 0;\nfun count() {}\n").unwrap();
+        let globals = repl.interpreter.globals();
+        assert_eq!(
+            completions("co", &globals.borrow()),
+            vec!["continue", "count", "counter"]
+        );
+        assert_eq!(completions("whi", &globals.borrow()), vec!["while"]);
+    }
+}
@@ -8,24 +8,52 @@ use crate::{
     stmt::{self, Stmt, StmtVisitor},
 };
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LoopType {
+    None,
+    Loop,
+}
+
 #[derive(Debug)]
 pub struct Resolver<'a> {
-    interpreter: &'a mut Interpreter<'a>,
+    interpreter: &'a mut Interpreter,
     // 用 Vec 来记录当前作用域的栈，栈中的每个元素代表一个块作用域的 Map
     // 作用域栈只用于局部作用域，解析器不会跟踪全局作用域，因为它们会在运行时动态改变
     // true/false 表示是否已定义
     scopes: Vec<HashMap<String, bool>>,
+    // 当前所在的函数、类和循环，用于检查 return、this、break 和 continue 的使用位置
+    current_function: FunctionType,
+    current_class: ClassType,
+    current_loop: LoopType,
 }
 
 impl<'a> Resolver<'a> {
-    pub fn new(interpreter: &'a mut Interpreter<'a>) -> Self {
+    pub fn new(interpreter: &'a mut Interpreter) -> Self {
         Self {
             interpreter,
             scopes: vec![],
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            current_loop: LoopType::None,
         }
     }
 
-    fn resolve_statements(&mut self, statements: &[stmt::StmtEnum]) -> Result<(), Error> {
+    pub fn resolve_statements(&mut self, statements: &[stmt::StmtEnum]) -> Result<(), Error> {
         for stmt in statements {
             stmt.accept(self)?;
         }
@@ -53,24 +81,52 @@ impl<'a> Resolver<'a> {
         }
     }
 
-    fn resolve_local(&mut self, name: &'a lex::Token) -> Result<(), Error> {
+    fn resolve_local(&mut self, name: &lex::Token) -> Result<(), Error> {
         for (i, scope) in self.scopes.iter().enumerate().rev() {
             if scope.contains_key(&name.lexeme) {
-                self.interpreter.resolve(name, i);
+                // 记录的是距离当前作用域的层数，而不是栈中的下标
+                self.interpreter.resolve(name, self.scopes.len() - 1 - i);
                 return Ok(());
             }
         }
         Ok(())
     }
 
-    fn resolve_function(&mut self, stmt: &stmt::FunctionDecl) -> Result<(), Error> {
+    fn resolve_function(
+        &mut self,
+        stmt: &stmt::FunctionDecl,
+        function_type: FunctionType,
+    ) -> Result<(), Error> {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+        // 函数体内的 break 和 continue 不能跳出函数外面的循环
+        let enclosing_loop = self.current_loop;
+        self.current_loop = LoopType::None;
+
         self.begin_scope();
+        let r = self
+            .resolve_parameters(stmt)
+            .and_then(|_| self.resolve_statements(&stmt.body.statements));
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+        self.current_loop = enclosing_loop;
+        r
+    }
+
+    /// 默认值在参数自身定义之前解析，因此只能引用前面的参数
+    fn resolve_parameters(&mut self, stmt: &stmt::FunctionDecl) -> Result<(), Error> {
         for param in &stmt.parameters {
-            self.declare(&param);
-            self.define(&param);
+            if let Some(default) = &param.default {
+                default.accept(self)?;
+            }
+            self.declare(&param.name);
+            self.define(&param.name);
+        }
+        if let Some(rest) = &stmt.rest {
+            self.declare(rest);
+            self.define(rest);
         }
-        self.resolve_statements(&stmt.body.statements)?;
-        self.end_scope();
         Ok(())
     }
 }
@@ -102,7 +158,7 @@ impl<'a> ExprVisitor for Resolver<'a> {
         if let Some(scope) = self.scopes.last() {
             if let Some(is_defined) = scope.get(&expr.name.lexeme) {
                 if !is_defined {
-                    return Err(Error::ParseError(
+                    return Err(Error::ResolveError(
                         expr.name.clone(),
                         "Can't read local variable in its own initializer".to_string(),
                     ));
@@ -133,6 +189,83 @@ impl<'a> ExprVisitor for Resolver<'a> {
         }
         Ok(())
     }
+
+    fn visit_get(&mut self, expr: &expr::Get) -> Self::Output {
+        // 属性是动态查找的，只需要解析对象表达式
+        expr.object.accept(self)?;
+        Ok(())
+    }
+
+    fn visit_set(&mut self, expr: &expr::Set) -> Self::Output {
+        expr.value.accept(self)?;
+        expr.object.accept(self)?;
+        Ok(())
+    }
+
+    fn visit_super(&mut self, expr: &expr::Super) -> Self::Output {
+        match self.current_class {
+            ClassType::None => Err(Error::ResolveError(
+                expr.keyword.clone(),
+                "Can't use 'super' outside of a class.".to_string(),
+            )),
+            ClassType::Class => Err(Error::ResolveError(
+                expr.keyword.clone(),
+                "Can't use 'super' in a class with no superclass.".to_string(),
+            )),
+            ClassType::Subclass => self.resolve_local(&expr.keyword),
+        }
+    }
+
+    fn visit_this(&mut self, expr: &expr::This) -> Self::Output {
+        if self.current_class == ClassType::None {
+            return Err(Error::ResolveError(
+                expr.keyword.clone(),
+                "Can't use 'this' outside of a class.".to_string(),
+            ));
+        }
+        self.resolve_local(&expr.keyword)?;
+        Ok(())
+    }
+
+    fn visit_list(&mut self, expr: &expr::List) -> Self::Output {
+        for element in &expr.elements {
+            element.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_map(&mut self, expr: &expr::Map) -> Self::Output {
+        for (key, _, value) in &expr.entries {
+            key.accept(self)?;
+            value.accept(self)?;
+        }
+        Ok(())
+    }
+
+    fn visit_index(&mut self, expr: &expr::Index) -> Self::Output {
+        expr.object.accept(self)?;
+        expr.index.accept(self)?;
+        Ok(())
+    }
+
+    fn visit_index_set(&mut self, expr: &expr::IndexSet) -> Self::Output {
+        expr.value.accept(self)?;
+        expr.object.accept(self)?;
+        expr.index.accept(self)?;
+        Ok(())
+    }
+
+    fn visit_lambda(&mut self, expr: &expr::Lambda) -> Self::Output {
+        // 匿名函数没有名字，不需要在当前作用域中声明
+        self.resolve_function(&expr.declaration, FunctionType::Function)
+    }
+
+    fn visit_interpolation(&mut self, expr: &expr::Interpolation) -> Self::Output {
+        for part in &expr.parts {
+            part.accept(self)?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a> StmtVisitor for Resolver<'a> {
@@ -175,21 +308,108 @@ impl<'a> StmtVisitor for Resolver<'a> {
 
     fn visit_while(&mut self, stmt: &stmt::While) -> Self::Output {
         stmt.condition.accept(self)?;
-        stmt.body.accept(self)?;
-        Ok(())
+        if let Some(increment) = &stmt.increment {
+            increment.accept(self)?;
+        }
+        let enclosing_loop = self.current_loop;
+        self.current_loop = LoopType::Loop;
+        let r = stmt.body.accept(self);
+        self.current_loop = enclosing_loop;
+        r
     }
 
     fn visit_function_decl(&mut self, stmt: &stmt::FunctionDecl) -> Self::Output {
         self.declare(&stmt.name);
         self.define(&stmt.name);
-        self.resolve_function(&stmt);
+        self.resolve_function(stmt, FunctionType::Function)?;
         Ok(())
     }
 
     fn visit_return(&mut self, stmt: &stmt::Return) -> Self::Output {
+        if self.current_function == FunctionType::None {
+            return Err(Error::ResolveError(
+                stmt.keyword.clone(),
+                "Can't return from top-level code.".to_string(),
+            ));
+        }
         if let Some(value) = &stmt.value {
+            if self.current_function == FunctionType::Initializer {
+                return Err(Error::ResolveError(
+                    stmt.keyword.clone(),
+                    "Can't return a value from an initializer.".to_string(),
+                ));
+            }
             value.accept(self)?;
         }
         Ok(())
     }
+
+    fn visit_break(&mut self, stmt: &stmt::Break) -> Self::Output {
+        if self.current_loop == LoopType::None {
+            return Err(Error::ResolveError(
+                stmt.keyword.clone(),
+                "Can't use 'break' outside of a loop.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn visit_continue(&mut self, stmt: &stmt::Continue) -> Self::Output {
+        if self.current_loop == LoopType::None {
+            return Err(Error::ResolveError(
+                stmt.keyword.clone(),
+                "Can't use 'continue' outside of a loop.".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn visit_class(&mut self, stmt: &stmt::Class) -> Self::Output {
+        let enclosing_class = self.current_class;
+        self.current_class = ClassType::Class;
+
+        self.declare(&stmt.name);
+        self.define(&stmt.name);
+
+        if let Some(superclass) = &stmt.superclass {
+            if superclass.name.lexeme == stmt.name.lexeme {
+                self.current_class = enclosing_class;
+                return Err(Error::ResolveError(
+                    superclass.name.clone(),
+                    "A class can't inherit from itself.".to_string(),
+                ));
+            }
+            self.current_class = ClassType::Subclass;
+            if let Err(e) = self.visit_variable(superclass) {
+                self.current_class = enclosing_class;
+                return Err(e);
+            }
+            // 与解释器保持一致，super 单独占一层作用域
+            self.begin_scope();
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.insert("super".to_string(), true);
+            }
+        }
+
+        // 方法体外再包一层作用域，用于绑定 this
+        self.begin_scope();
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert("this".to_string(), true);
+        }
+        let r = stmt.methods.iter().try_for_each(|method| {
+            let function_type = if method.name.lexeme == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(method, function_type)
+        });
+        self.end_scope();
+        if stmt.superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class = enclosing_class;
+        r
+    }
 }
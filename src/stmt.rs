@@ -1,46 +1,70 @@
-use lox_macro::New;
-
-use crate::{expr::ExprEnum, lex::Token};
-
-pub trait StmtVisitor {
-    type Output;
-    fn visit_expression(&mut self, stmt: &Expression) -> Self::Output;
-    fn visit_print(&mut self, stmt: &Print) -> Self::Output;
-    fn visit_var_decl(&mut self, stmt: &VarDecl) -> Self::Output;
-    fn visit_block(&mut self, stmt: &Block) -> Self::Output;
-    fn visit_if(&mut self, stmt: &If) -> Self::Output;
-    fn visit_while(&mut self, stmt: &While) -> Self::Output;
-    fn visit_function_decl(&mut self, stmt: &FunctionDecl) -> Self::Output;
-    fn visit_return(&mut self, stmt: &Return) -> Self::Output;
-}
+use lox_macro::{ast, New};
+
+use crate::{
+    expr::{ExprEnum, Variable},
+    lex::{Span, Token},
+};
 
 pub trait Stmt {
     fn accept<R>(&self, visitor: &mut dyn StmtVisitor<Output = R>) -> R;
 }
 
+/// 所有语句节点，新增节点时在这里加一行并定义同名结构体
+#[ast(visitor = StmtVisitor, accept = Stmt)]
 #[derive(Debug, Clone)]
 pub enum StmtEnum {
-    Expression(Expression),
-    Print(Print),
-    VarDecl(VarDecl),
-    Block(Block),
-    If(If),
-    While(While),
-    FunctionDecl(FunctionDecl),
-    Return(Return),
+    Expression,
+    Print,
+    VarDecl,
+    Block,
+    If,
+    While,
+    FunctionDecl,
+    Return,
+    Break,
+    Continue,
+    Class,
 }
 
-impl Stmt for StmtEnum {
-    fn accept<R>(&self, visitor: &mut dyn StmtVisitor<Output = R>) -> R {
+impl StmtEnum {
+    /// 声明的文档注释，只有变量、函数和类声明可以带文档注释
+    pub fn doc(&self) -> Option<&str> {
         match self {
-            Self::Expression(stmt) => visitor.visit_expression(stmt),
-            Self::Print(stmt) => visitor.visit_print(stmt),
-            Self::VarDecl(stmt) => visitor.visit_var_decl(stmt),
-            Self::Block(stmt) => visitor.visit_block(stmt),
-            Self::If(stmt) => visitor.visit_if(stmt),
-            Self::While(stmt) => visitor.visit_while(stmt),
-            Self::FunctionDecl(stmt) => visitor.visit_function_decl(stmt),
-            Self::Return(stmt) => visitor.visit_return(stmt),
+            Self::VarDecl(stmt) => stmt.doc.as_deref(),
+            Self::FunctionDecl(stmt) => stmt.doc.as_deref(),
+            Self::Class(stmt) => stmt.doc.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// 语句在源码中覆盖的区间，不包含结尾的分号
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Expression(stmt) => stmt.expression.span(),
+            Self::Print(stmt) => stmt.keyword.span.merge(&stmt.expression.span()),
+            Self::VarDecl(stmt) => match &stmt.initializer {
+                Some(initializer) => stmt.name.span.merge(&initializer.span()),
+                None => stmt.name.span,
+            },
+            Self::Block(stmt) => stmt.span,
+            Self::If(stmt) => {
+                let then_span = stmt.keyword.span.merge(&stmt.then_branch.span());
+                match &stmt.else_branch {
+                    Some(else_branch) => then_span.merge(&else_branch.span()),
+                    None => then_span,
+                }
+            }
+            Self::While(stmt) => stmt.keyword.span.merge(&stmt.body.span()),
+            Self::FunctionDecl(stmt) => stmt.name.span.merge(&stmt.body.span),
+            Self::Return(stmt) => match &stmt.value {
+                Some(value) => stmt.keyword.span.merge(&value.span()),
+                None => stmt.keyword.span,
+            },
+            Self::Break(stmt) => stmt.keyword.span,
+            Self::Continue(stmt) => stmt.keyword.span,
+            Self::Class(stmt) => stmt.methods.iter().fold(stmt.name.span, |span, method| {
+                span.merge(&method.name.span.merge(&method.body.span))
+            }),
         }
     }
 }
@@ -52,6 +76,7 @@ pub struct Expression {
 
 #[derive(New, Debug, Clone)]
 pub struct Print {
+    pub keyword: Token,
     pub expression: Box<ExprEnum>,
 }
 
@@ -59,15 +84,19 @@ pub struct Print {
 pub struct VarDecl {
     pub name: Token,
     pub initializer: Option<Box<ExprEnum>>,
+    // 声明前的 /// 文档注释，多行之间以换行连接
+    pub doc: Option<String>,
 }
 
 #[derive(New, Debug, Clone)]
 pub struct Block {
     pub statements: Vec<StmtEnum>,
+    pub span: Span, // 包含左右花括号
 }
 
 #[derive(New, Debug, Clone)]
 pub struct If {
+    pub keyword: Token,
     pub condition: Box<ExprEnum>,
     pub then_branch: Box<StmtEnum>,
     pub else_branch: Option<Box<StmtEnum>>,
@@ -75,15 +104,28 @@ pub struct If {
 
 #[derive(New, Debug, Clone)]
 pub struct While {
+    pub keyword: Token,
     pub condition: Box<ExprEnum>,
     pub body: Box<StmtEnum>,
+    // for 循环脱糖后的递增子句，每次迭代结束（包括 continue）后执行
+    pub increment: Option<Box<ExprEnum>>,
 }
 
 #[derive(New, Debug, Clone)]
 pub struct FunctionDecl {
     pub name: Token,
-    pub parameters: Vec<Token>,
+    pub parameters: Vec<Parameter>,
+    // 剩余参数，多出的实参收集为一个列表
+    pub rest: Option<Token>,
     pub body: Block,
+    pub doc: Option<String>,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Parameter {
+    pub name: Token,
+    // 默认值在调用时于函数作用域中求值，可以引用前面的参数
+    pub default: Option<ExprEnum>,
 }
 
 #[derive(New, Debug, Clone)]
@@ -91,3 +133,21 @@ pub struct Return {
     pub keyword: Token,
     pub value: Option<Box<ExprEnum>>,
 }
+
+#[derive(New, Debug, Clone)]
+pub struct Break {
+    pub keyword: Token,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Continue {
+    pub keyword: Token,
+}
+
+#[derive(New, Debug, Clone)]
+pub struct Class {
+    pub name: Token,
+    pub superclass: Option<Variable>,
+    pub methods: Vec<FunctionDecl>,
+    pub doc: Option<String>,
+}